@@ -121,6 +121,22 @@ documentation = {
         category = "ui"
     },
     
+    follow = {
+        name = "follow",
+        description = "Pause or resume following new records. Resuming jumps to the tail. Without argument it toggles",
+        parameters = {"following (boolean, optional) - Whether to follow"},
+        return_value = "boolean - Whether following",
+        category = "ui"
+    },
+    
+    toggle_binary_display = {
+        name = "toggle_binary_display",
+        description = "Cycle how control characters and invalid bytes are shown: as read, as \\x07 escapes or as <07> hex",
+        parameters = {},
+        return_value = "none",
+        category = "ui"
+    },
+    
     lua_repl = {
         name = "lua_repl",
         description = "Enter Lua REPL mode",
@@ -178,6 +194,20 @@ documentation = {
         return_value = "number - Number of records after filtering",
         category = "filtering"
     },
+    save_filter = {
+        name = "save_filter",
+        description = "Save the current filter with a name in the current rule, and in ~/.config/tailtales/filters.yaml",
+        parameters = {"name (string) - Name of the filter"},
+        return_value = "none",
+        category = "filtering"
+    },
+    load_filter = {
+        name = "load_filter",
+        description = "Apply a named filter of the current rule, saved or from the settings",
+        parameters = {"name (string) - Name of the filter"},
+        return_value = "none",
+        category = "filtering"
+    },
     
     -- Record Access Functions
     get_record = {
@@ -1,6 +1,6 @@
 use notify::{Event as NotifyEvent, RecommendedWatcher, RecursiveMode, Watcher};
 use std::sync::mpsc;
-use std::{cmp::max, io, time};
+use std::{io, time};
 
 use crate::keyboard_management::handle_key_event;
 use crate::{events::TuiEvent, lua_engine::LuaEngine, state::TuiState, tuichrome::TuiChrome};
@@ -156,15 +156,40 @@ impl Application {
                     }
                 },
                 TuiEvent::NewRecord(record) => {
+                    let (len, dropped_visible) =
+                        (self.state.records.len(), self.state.records.dropped_visible);
                     self.state
                         .records
                         .add_record(record, Some(&mut self.lua_engine));
-                    if self.state.position == max(0, self.state.records.len() as i32 - 2) as usize {
-                        self.state.move_selection(1);
-                    }
+                    let dropped = self.state.records.dropped_visible - dropped_visible;
+                    self.state.records_dropped(dropped);
+                    self.state
+                        .records_added(self.state.records.len() + dropped - len);
                     // self.wait_for_event_timeout(time::Duration::from_millis(100))?;
                     timeout = time::Duration::from_millis(100);
                 }
+                TuiEvent::NewRecords(records) => {
+                    let (len, dropped_visible) =
+                        (self.state.records.len(), self.state.records.dropped_visible);
+                    self.state.records.add_records(records);
+                    let dropped = self.state.records.dropped_visible - dropped_visible;
+                    self.state.records_dropped(dropped);
+                    // Loading does not move the selection, only counted if paused
+                    if !self.state.following {
+                        self.state
+                            .records_added(self.state.records.len() + dropped - len);
+                    }
+                    timeout = time::Duration::from_millis(100);
+                }
+                TuiEvent::LoadProgress(progress) => {
+                    self.state.set_load_progress(progress);
+                    timeout = time::Duration::from_millis(100);
+                }
+                TuiEvent::ClearRecords(source) => {
+                    self.state.records.clear_source(source.as_deref());
+                    self.state.move_selection(0);
+                    timeout = time::Duration::from_millis(100);
+                }
             }
             events_received += 1;
             if events_received > 100 {
@@ -9,6 +9,13 @@ pub struct ParsedArgs {
     pub rule: Option<String>,
     pub files: Vec<String>,
     pub lua_script: Option<String>,
+    pub lazy: bool,
+    pub max_records: Option<usize>,
+    pub listen: Vec<String>,
+    pub lines: Option<usize>,
+    pub bytes: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
 }
 
 /// Parse command line arguments using clap
@@ -50,6 +57,53 @@ pub fn parse_args_with_clap(args: Vec<String>) -> ParsedArgs {
                 .value_name("SCRIPT")
                 .help("Execute a Lua script file before processing logs"),
         )
+        .arg(
+            Arg::new("lazy")
+                .long("lazy")
+                .action(clap::ArgAction::SetTrue)
+                .help("Map files instead of reading them, and parse lines only when needed"),
+        )
+        .arg(
+            Arg::new("max-records")
+                .long("max-records")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Keep only the last N records of streamed input"),
+        )
+        .arg(
+            Arg::new("listen")
+                .long("listen")
+                .value_name("ADDRESS")
+                .action(clap::ArgAction::Append)
+                .help("Receive log messages at udp://host:port, tcp://host:port, unix:///path or unixgram:///path"),
+        )
+        .arg(
+            Arg::new("lines")
+                .short('n')
+                .long("lines")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Read only the last N lines of the file, older ones when scrolling up"),
+        )
+        .arg(
+            Arg::new("bytes")
+                .long("bytes")
+                .value_name("SIZE")
+                .conflicts_with("lines")
+                .help("Read only the last SIZE (as 50M) of the file, older lines when scrolling up"),
+        )
+        .arg(
+            Arg::new("since")
+                .long("since")
+                .value_name("TIME")
+                .help("Read only the records from TIME (as \"2025-03-01 10:00\") of a file sorted by time"),
+        )
+        .arg(
+            Arg::new("until")
+                .long("until")
+                .value_name("TIME")
+                .help("Read only the records up to TIME (as \"10:30\", on the --since day) of a file sorted by time"),
+        )
         .arg(
             Arg::new("files")
                 .num_args(0..)
@@ -67,6 +121,16 @@ pub fn parse_args_with_clap(args: Vec<String>) -> ParsedArgs {
 
     let rule = matches.get_one::<String>("rule").cloned();
     let lua_script = matches.get_one::<String>("lua").cloned();
+    let lazy = matches.get_flag("lazy");
+    let max_records = matches.get_one::<usize>("max-records").copied();
+    let lines = matches.get_one::<usize>("lines").copied();
+    let bytes = matches.get_one::<String>("bytes").cloned();
+    let since = matches.get_one::<String>("since").cloned();
+    let until = matches.get_one::<String>("until").cloned();
+    let listen: Vec<String> = matches
+        .get_many::<String>("listen")
+        .map(|addresses| addresses.cloned().collect())
+        .unwrap_or_default();
     let mut files: Vec<String> = matches
         .get_many::<String>("files")
         .map(|f| f.cloned().collect())
@@ -82,5 +146,12 @@ pub fn parse_args_with_clap(args: Vec<String>) -> ParsedArgs {
         rule,
         files,
         lua_script,
+        lazy,
+        max_records,
+        listen,
+        lines,
+        bytes,
+        since,
+        until,
     }
 }
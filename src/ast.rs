@@ -1,11 +1,26 @@
-use crate::{record::Record, regex_cache::REGEX_CACHE};
+use std::fmt;
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::Chars;
+
+use chrono::{DateTime, NaiveTime, SecondsFormat, Utc};
+
+use crate::value;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum AST {
     Variable(String),
     String(String),
     Number(i64),
-    Boolean(bool),
+    Float(f64),
+    /// In seconds
+    Duration(f64),
+    Bytes(u64),
+    /// A time of the day, as `10:30`
+    Time(NaiveTime),
+    Timestamp(DateTime<Utc>),
+    /// The current time, when evaluated
+    Now,
     Equal(Box<AST>, Box<AST>),
     GreaterEqual(Box<AST>, Box<AST>),
     Greater(Box<AST>, Box<AST>),
@@ -14,6 +29,14 @@ pub enum AST {
     Not(Box<AST>),
     And(Box<AST>, Box<AST>),
     Or(Box<AST>, Box<AST>),
+    Add(Box<AST>, Box<AST>),
+    Subtract(Box<AST>, Box<AST>),
+    /// `value in from..to`, both included
+    InRange(Box<AST>, Box<AST>, Box<AST>),
+    /// `value in [a, b]`
+    InList(Box<AST>, Vec<AST>),
+    /// One of the `FUNCTIONS`, with its arguments
+    Call(String, Vec<AST>),
     RegCompareBinary(Box<AST>, Box<AST>),
     RegCompareUnary(Box<AST>),
     Empty,
@@ -25,48 +48,128 @@ impl Default for AST {
     }
 }
 
-pub fn parse(input: &str) -> Result<AST, String> {
-    let mut tokens = match tokenize(input) {
-        Ok(tokens) => tokens,
-        Err(_e) => return Ok(AST::String(input.to_string())),
+/// A syntax error, with the bytes of the input it is about.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Range<usize>,
+}
+
+impl ParseError {
+    fn new(message: String, span: Range<usize>) -> ParseError {
+        ParseError { message, span }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.span.start + 1)
+    }
+}
+
+/// Parses a filter. Input with characters that are not part of the language, as
+/// `/api/users` or `user@host`, is not an error but a plain text search.
+pub fn parse(input: &str) -> Result<AST, ParseError> {
+    let Some(mut tokens) = tokenize(input) else {
+        return Ok(AST::String(input.to_string()));
     };
     // We convert simple variables to strings. If want the variable as it must exist, use !!var
     match parse_expression(&mut tokens) {
         Ok(AST::Variable(var)) => Ok(AST::String(var)),
+        // Also literals with units and arithmetic, as `GET - 200`, to look for them as
+        // written
+        Ok(
+            AST::Float(_)
+            | AST::Duration(_)
+            | AST::Bytes(_)
+            | AST::Time(_)
+            | AST::Timestamp(_)
+            | AST::Add(_, _)
+            | AST::Subtract(_, _),
+        ) => Ok(AST::String(input.trim().to_string())),
         Ok(ast) => Ok(ast),
         Err(e) => Err(e),
     }
 }
 
 impl AST {
-    pub fn from_str(input: &str) -> Result<AST, String> {
-        parse(input)
-    }
-
+    /// The expression as text that parses back to the same AST. Parentheses are only
+    /// added where the precedence needs them.
     pub fn to_string(&self) -> String {
+        let precedence = self.precedence();
+        // Operands of the same precedence on the right need them, as operators are
+        // left associative.
+        let lhs = |ast: &AST| ast.to_string_within(precedence);
+        let rhs = |ast: &AST| ast.to_string_within(precedence + 1);
         match self {
-            AST::Variable(var) => format!("\"{}\"", var),
-            AST::String(s) => s.clone(),
+            AST::Variable(var) => var.clone(),
+            AST::String(s) => format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
             AST::Number(n) => n.to_string(),
-            AST::Boolean(b) => b.to_string(),
-            AST::Equal(lhs, rhs) => format!("{} == {}", lhs.to_string(), rhs.to_string()),
-            AST::GreaterEqual(lhs, rhs) => format!("{} >= {}", lhs.to_string(), rhs.to_string()),
-            AST::Greater(lhs, rhs) => format!("{} > {}", lhs.to_string(), rhs.to_string()),
-            AST::LessEqual(lhs, rhs) => format!("{} <= {}", lhs.to_string(), rhs.to_string()),
-            AST::Less(lhs, rhs) => format!("{} < {}", lhs.to_string(), rhs.to_string()),
-            AST::Not(ast) => format!("!{}", ast.to_string()),
-            AST::And(lhs, rhs) => format!("{} && {}", lhs.to_string(), rhs.to_string()),
-            AST::Or(lhs, rhs) => format!("{} || {}", lhs.to_string(), rhs.to_string()),
-            AST::RegCompareBinary(lhs, rhs) => format!("{} ~ {}", lhs.to_string(), rhs.to_string()),
-            AST::RegCompareUnary(ast) => format!("~{}", ast.to_string()),
+            AST::Float(f) if f.fract() == 0.0 => format!("{}.0", f),
+            AST::Float(f) => f.to_string(),
+            AST::Duration(seconds) => format_duration(*seconds),
+            AST::Bytes(bytes) => format!("{}B", bytes),
+            AST::Time(time) => time.to_string(),
+            AST::Timestamp(timestamp) => timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            AST::Now => "now".to_string(),
+            AST::Equal(a, b) => format!("{} == {}", lhs(a), rhs(b)),
+            AST::GreaterEqual(a, b) => format!("{} >= {}", lhs(a), rhs(b)),
+            AST::Greater(a, b) => format!("{} > {}", lhs(a), rhs(b)),
+            AST::LessEqual(a, b) => format!("{} <= {}", lhs(a), rhs(b)),
+            AST::Less(a, b) => format!("{} < {}", lhs(a), rhs(b)),
+            AST::Not(ast) => format!("!{}", lhs(ast)),
+            AST::And(a, b) => format!("{} && {}", lhs(a), rhs(b)),
+            AST::Or(a, b) => format!("{} || {}", lhs(a), rhs(b)),
+            AST::Add(a, b) => format!("{} + {}", lhs(a), rhs(b)),
+            AST::Subtract(a, b) => format!("{} - {}", lhs(a), rhs(b)),
+            AST::InRange(value, from, to) => {
+                format!("{} in {}..{}", lhs(value), rhs(from), rhs(to))
+            }
+            AST::InList(value, items) => format!("{} in [{}]", lhs(value), join(items)),
+            AST::Call(name, args) => format!("{}({})", name, join(args)),
+            AST::RegCompareBinary(a, b) => format!("{} ~ {}", lhs(a), rhs(b)),
+            AST::RegCompareUnary(ast) => format!("~{}", lhs(ast)),
             AST::Empty => "".to_string(),
         }
     }
+
+    /// As an operand of an operator of `precedence`.
+    fn to_string_within(&self, precedence: u8) -> String {
+        if self.precedence() < precedence {
+            format!("({})", self.to_string())
+        } else {
+            self.to_string()
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            AST::Or(_, _) => OR_PRECEDENCE,
+            AST::And(_, _) => AND_PRECEDENCE,
+            AST::Not(_) => NOT_PRECEDENCE,
+            AST::Equal(_, _)
+            | AST::GreaterEqual(_, _)
+            | AST::Greater(_, _)
+            | AST::LessEqual(_, _)
+            | AST::Less(_, _)
+            | AST::RegCompareBinary(_, _)
+            | AST::InRange(_, _, _)
+            | AST::InList(_, _) => COMPARISON_PRECEDENCE,
+            AST::Add(_, _) | AST::Subtract(_, _) => ADDITIVE_PRECEDENCE,
+            _ => TERM_PRECEDENCE,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
 enum Token {
     Number(i64),
+    Float(f64),
+    /// In seconds
+    Duration(f64),
+    Bytes(u64),
+    Time(NaiveTime),
+    Timestamp(DateTime<Utc>),
     Variable(String),
     String(String),
     // Boolean(bool),
@@ -79,6 +182,15 @@ enum Token {
     Not,
     And,
     Or,
+    OpenParen,
+    CloseParen,
+    In,
+    Range,
+    OpenBracket,
+    CloseBracket,
+    Comma,
+    Plus,
+    Minus,
 }
 
 fn is_variable_continuation_char(c: char) -> bool {
@@ -93,8 +205,111 @@ fn should_parse_as_variable(next_char: char) -> bool {
         || next_char.is_alphabetic()
 }
 
-fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+/// A duration in the largest unit that parses back to the same value, as `5m`.
+fn format_duration(seconds: f64) -> String {
+    let units = [
+        ("d", 86400.0),
+        ("h", 3600.0),
+        ("m", 60.0),
+        ("s", 1.0),
+        ("ms", 1e-3),
+        ("us", 1e-6),
+        ("ns", 1e-9),
+    ];
+    units
+        .iter()
+        .map(|(unit, size)| format!("{}{}", seconds / size, unit))
+        .find(|text| !text.contains('.') && value::parse_duration(text) == Some(seconds))
+        .unwrap_or_else(|| format!("{}s", seconds))
+}
+
+/// Adds the rest of a word. Numbers, as `10:02..10:07`, end at a `..` range.
+fn take_word(word: &mut String, chars: &mut Peekable<Chars>, number: bool) {
+    while let Some(&c) = chars.peek() {
+        let range = number && c == '.' && chars.clone().nth(1) == Some('.');
+        if !is_variable_continuation_char(c) || range {
+            break;
+        }
+        word.push(c);
+        chars.next();
+    }
+}
+
+/// A word that starts with a digit: a number, a float, a duration as `250ms` or `1h30m`,
+/// a size as `2MB` or `1KiB`, or else a variable as `2024-01` or `123abc`.
+fn number_token(word: String) -> Token {
+    if let Ok(n) = word.parse() {
+        return Token::Number(n);
+    }
+    if word.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        if let Ok(f) = word.parse() {
+            return Token::Float(f);
+        }
+    }
+    if word.ends_with(char::is_alphabetic) {
+        // `m` is minutes, sizes in megabytes are `MB`
+        if let Some(seconds) = value::parse_duration(&word) {
+            return Token::Duration(seconds);
+        }
+        if let Some(bytes) = value::parse_bytes(&word) {
+            return Token::Bytes(bytes);
+        }
+    }
+    if let Some(time) = value::parse_time(&word) {
+        return Token::Time(time);
+    }
+    if let Some(timestamp) = value::parse_timestamp(&word) {
+        return Token::Timestamp(timestamp);
+    }
+    Token::Variable(word)
+}
+
+/// Tokens left to parse, with their spans in the input.
+#[derive(Debug)]
+struct Tokens {
+    tokens: Vec<Token>,
+    spans: Vec<Range<usize>>,
+    /// Span of the last token taken
+    last: Range<usize>,
+    /// Length of the input, for the errors at its end
+    end: usize,
+}
+
+impl Tokens {
+    fn first(&self) -> Option<&Token> {
+        self.tokens.first()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        if self.tokens.is_empty() {
+            return None;
+        }
+        self.last = self.spans.remove(0);
+        Some(self.tokens.remove(0))
+    }
+
+    /// An error at the next token, or at the end of the input.
+    fn error(&self, message: String) -> ParseError {
+        let span = self.spans.first().cloned().unwrap_or(self.end..self.end);
+        ParseError::new(message, span)
+    }
+}
+
+/// Byte offset of the next char of the input.
+fn offset(input: &str, chars: &Peekable<Chars>) -> usize {
+    input.len() - chars.clone().map(char::len_utf8).sum::<usize>()
+}
+
+/// The tokens of the input, or None if it has characters that are not part of the
+/// language.
+fn tokenize(input: &str) -> Option<Tokens> {
     let mut tokens = Vec::new();
+    let mut spans = Vec::new();
+    let mut start = 0;
     let mut chars = input.chars().peekable();
     while let Some(c) = chars.next() {
         match c {
@@ -109,48 +324,47 @@ fn tokenize(input: &str) -> Result<Vec<Token>, String> {
                     }
                 }
 
-                // Check if this is followed by non-digit characters that would make it a variable
-                if let Some(&next_char) = chars.peek() {
-                    if should_parse_as_variable(next_char) {
-                        // Parse as variable instead
-                        while let Some(&c) = chars.peek() {
-                            if is_variable_continuation_char(c) {
-                                num.push(c);
-                                chars.next();
-                            } else {
-                                break;
-                            }
-                        }
-                        tokens.push(Token::Variable(num));
-                    } else {
-                        tokens.push(Token::Number(num.parse().unwrap()));
-                    }
-                } else {
-                    tokens.push(Token::Number(num.parse().unwrap()));
+                // Followed by units, decimals or other characters that may make it a variable
+                if chars.peek().is_some_and(|&c| should_parse_as_variable(c)) {
+                    take_word(&mut num, &mut chars, true);
                 }
+                tokens.push(number_token(num));
             }
             'a'..='z' | 'A'..='Z' => {
                 let mut var = c.to_string();
-                while let Some(&c) = chars.peek() {
-                    if is_variable_continuation_char(c) {
-                        var.push(c);
-                        chars.next();
-                    } else {
-                        break;
-                    }
+                take_word(&mut var, &mut chars, false);
+                match var.as_str() {
+                    "in" => tokens.push(Token::In),
+                    _ => tokens.push(Token::Variable(var)),
                 }
-                tokens.push(Token::Variable(var));
+            }
+            '.' if chars.peek() == Some(&'.') => {
+                chars.next();
+                tokens.push(Token::Range);
+            }
+            // Only as operators when apart, so `-v` is still looked for as text
+            '-' | '+' if chars.peek().is_some_and(|c| c.is_whitespace()) => {
+                tokens.push(if c == '-' { Token::Minus } else { Token::Plus });
             }
             '"' => {
                 let mut string = String::new();
                 while let Some(c) = chars.next() {
-                    if c == '"' {
-                        break;
+                    match c {
+                        '"' => break,
+                        // Other escapes are kept, as `\d` in regexes
+                        '\\' if matches!(chars.peek(), Some('"' | '\\')) => {
+                            string.push(chars.next().unwrap())
+                        }
+                        _ => string.push(c),
                     }
-                    string.push(c);
                 }
                 tokens.push(Token::String(string));
             }
+            '(' => tokens.push(Token::OpenParen),
+            '[' => tokens.push(Token::OpenBracket),
+            ']' => tokens.push(Token::CloseBracket),
+            ',' => tokens.push(Token::Comma),
+            ')' => tokens.push(Token::CloseParen),
             '>' => {
                 // >=
                 if let Some(&'=') = chars.peek() {
@@ -195,365 +409,315 @@ fn tokenize(input: &str) -> Result<Vec<Token>, String> {
                 }
             }
             ' ' => {}
-            _ => {
-                return Err(format!("unexpected character {:?}", c));
-            }
+            _ => return None,
+        }
+        let end = offset(input, &chars);
+        if spans.len() < tokens.len() {
+            spans.push(start..end);
         }
+        start = end;
     }
-    Ok(tokens)
+    Some(Tokens {
+        tokens,
+        spans,
+        last: 0..0,
+        end: input.len(),
+    })
 }
 
 /**
- * BNF
+ * Grammar, from the lowest to the highest precedence, as parsed by `parse_expr` with
+ * the precedences of `binary_precedence`. Binary operators are left associative.
  *
  * start: <expr> END
+ *      | END
+ *
+ * expr: <and> (<or_op> <and>)*                                   OR_PRECEDENCE
+ *
+ * and: <not> (<and_op> <not>)*                                   AND_PRECEDENCE
+ *
+ * or_op: || | |        and_op: && | &
+ *
+ * not: ! <not>                                                   NOT_PRECEDENCE
+ *    | <comparison>
+ *
+ *   So `!a == 1` is `!(a == 1)`. Not allowed after a comparison or additive
+ *   operator, where it would bind tighter: `a == !b` is an error.
+ *
+ * comparison: <additive> (<comparison_op> <additive>)*           COMPARISON_PRECEDENCE
+ *           | <comparison> in <additive> .. <additive>
+ *           | <comparison> in [ <list> ]
  *
- * expr: <term>
- *     | <term> <binary_op>
- *     | <unary_op>
+ * comparison_op: > | >= | < | <= | == | = | ~
  *
- * term: <number>
- *      | <string>
- *      | <variable>
+ * additive: <unary> ((+ | -) <unary>)*                           ADDITIVE_PRECEDENCE
  *
- * binary_op: <greater> <expr>
- *          | <greater_than> <expr>
- *          | <less> <expr>
- *          | <less_than> <expr>
- *          | <equal> <expr>
- *          | <and> <expr>
- *          | <or> <expr>
- *          | <regexp> <expr>
+ *   `+` and `-` only when followed by a space, else they are part of a word.
  *
- * unary_op: <not> <term>
- *         | <regexp> <term>
+ * unary: ~ <unary>
+ *      | <term>
+ *
+ * term: <number> | <float> | <duration> | <bytes> | <time> | <timestamp>
+ *     | <string>
+ *     | now
+ *     | in                     (as a word to look for)
+ *     | <function> ( <list> )  (one of FUNCTIONS)
+ *     | <variable>
+ *     | ( <expr> )
+ *
+ * list: (<expr> (, <expr>)*)?
  */
 
-fn parse_expression(tokens: &mut Vec<Token>) -> Result<AST, String> {
-    let ast = parse_expr(tokens)?;
-    if tokens.len() > 0 {
-        return Err(format!(
-            "unexpected token {:?} (expected term or unary)",
-            tokens[0]
-        ));
+const OR_PRECEDENCE: u8 = 1;
+const AND_PRECEDENCE: u8 = 2;
+const NOT_PRECEDENCE: u8 = 3;
+const COMPARISON_PRECEDENCE: u8 = 4;
+const ADDITIVE_PRECEDENCE: u8 = 5;
+const TERM_PRECEDENCE: u8 = 6;
+
+fn parse_expression(tokens: &mut Tokens) -> Result<AST, ParseError> {
+    if tokens.is_empty() {
+        return Ok(AST::Empty);
+    }
+    let ast = parse_expr(tokens, OR_PRECEDENCE)?;
+    if let Some(token) = tokens.first() {
+        return Err(tokens.error(format!("unexpected token {:?} (expected operator)", token)));
     }
     Ok(ast)
 }
 
-fn parse_expr(tokens: &mut Vec<Token>) -> Result<AST, String> {
-    if tokens.len() == 0 {
-        return Ok(AST::Boolean(true));
-    }
-    let ast = if next_is_unary_op(tokens) {
-        let ast = parse_unary_op(tokens);
-        return ast;
+/// Precedence climbing: parses the operators of at least `min_precedence`.
+fn parse_expr(tokens: &mut Tokens, min_precedence: u8) -> Result<AST, ParseError> {
+    let mut lhs = if min_precedence <= NOT_PRECEDENCE && tokens.first() == Some(&Token::Not) {
+        tokens.next();
+        AST::Not(Box::new(parse_expr(tokens, NOT_PRECEDENCE)?))
     } else {
-        parse_term(tokens)
+        parse_unary(tokens)?
     };
 
-    if next_is_binary_op(tokens) {
-        let ast = parse_binary_op(tokens, ast?);
-        return ast;
-    }
-    return ast;
-}
-
-fn parse_term(tokens: &mut Vec<Token>) -> Result<AST, String> {
-    if tokens.len() == 0 {
-        return Ok(AST::Empty);
-    }
-
-    match tokens.remove(0) {
-        Token::Number(n) => Ok(AST::Number(n)),
-        Token::Variable(v) => Ok(AST::Variable(v)),
-        Token::String(s) => Ok(AST::String(s)),
-        token => Err(format!("unexpected token {:?} (expected term)", token)),
-    }
-}
-
-fn next_is_unary_op(tokens: &Vec<Token>) -> bool {
-    if tokens.len() == 0 {
-        return false;
-    }
-    match tokens[0] {
-        Token::Not => true,
-        Token::RegCompare => true,
-        _ => false,
+    while let Some(precedence) = tokens.first().and_then(binary_precedence) {
+        if precedence < min_precedence {
+            break;
+        }
+        let Some(token) = tokens.next() else {
+            break;
+        };
+        let span = tokens.last.clone();
+        if token == Token::In && tokens.first() == Some(&Token::OpenBracket) {
+            tokens.next();
+            let items = parse_list(tokens, Token::CloseBracket)?;
+            lhs = AST::InList(Box::new(lhs), items);
+            continue;
+        }
+        let (lhs_ast, rhs) = (Box::new(lhs), Box::new(parse_expr(tokens, precedence + 1)?));
+        lhs = match token {
+            Token::In => match tokens.first() {
+                Some(Token::Range) => {
+                    tokens.next();
+                    let to = parse_expr(tokens, precedence + 1)?;
+                    AST::InRange(lhs_ast, rhs, Box::new(to))
+                }
+                _ => return Err(tokens.error("expected from..to or [a, b] after in".to_string())),
+            },
+            Token::Plus => AST::Add(lhs_ast, rhs),
+            Token::Minus => AST::Subtract(lhs_ast, rhs),
+            Token::Or => AST::Or(lhs_ast, rhs),
+            Token::And => AST::And(lhs_ast, rhs),
+            Token::Equal => AST::Equal(lhs_ast, rhs),
+            Token::Greater => AST::Greater(lhs_ast, rhs),
+            Token::GreaterEqual => AST::GreaterEqual(lhs_ast, rhs),
+            Token::Less => AST::Less(lhs_ast, rhs),
+            Token::LessEqual => AST::LessEqual(lhs_ast, rhs),
+            Token::RegCompare => AST::RegCompareBinary(lhs_ast, rhs),
+            token => {
+                return Err(ParseError::new(
+                    format!("unexpected token {:?} (expected binary token)", token),
+                    span,
+                ))
+            }
+        };
     }
+    Ok(lhs)
 }
 
-fn next_is_binary_op(tokens: &Vec<Token>) -> bool {
-    if tokens.len() == 0 {
-        return false;
-    }
-    match tokens[0] {
-        Token::GreaterEqual => true,
-        Token::LessEqual => true,
-        Token::Equal => true,
-        Token::Greater => true,
-        Token::Less => true,
-        Token::And => true,
-        Token::Or => true,
-        Token::RegCompare => true,
-        _ => false,
+fn binary_precedence(token: &Token) -> Option<u8> {
+    match token {
+        Token::Or => Some(OR_PRECEDENCE),
+        Token::And => Some(AND_PRECEDENCE),
+        Token::Equal
+        | Token::Greater
+        | Token::GreaterEqual
+        | Token::Less
+        | Token::LessEqual
+        | Token::RegCompare
+        | Token::In => Some(COMPARISON_PRECEDENCE),
+        Token::Plus | Token::Minus => Some(ADDITIVE_PRECEDENCE),
+        _ => None,
     }
 }
 
-fn parse_unary_op(tokens: &mut Vec<Token>) -> Result<AST, String> {
-    if tokens.len() == 0 {
-        return Err("unexpected end of expression".to_string());
-    }
-    match tokens.remove(0) {
-        Token::Not => {
-            let ast = parse_expr(tokens)?;
-            Ok(AST::Not(Box::new(ast)))
+fn parse_unary(tokens: &mut Tokens) -> Result<AST, ParseError> {
+    match tokens.first() {
+        Some(Token::RegCompare) => {
+            tokens.next();
+            Ok(AST::RegCompareUnary(Box::new(parse_unary(tokens)?)))
         }
-        Token::RegCompare => {
-            let ast = parse_expr(tokens)?;
-            Ok(AST::RegCompareUnary(Box::new(ast)))
-        }
-        token => Err(format!(
-            "unexpected token {:?} (expected unary token)",
-            token
-        )),
+        _ => parse_term(tokens),
     }
 }
 
-fn parse_binary_op(tokens: &mut Vec<Token>, lhs: AST) -> Result<AST, String> {
-    if tokens.len() == 0 {
-        return Err("unexpected end of expression".to_string());
-    }
-    match tokens.remove(0) {
-        Token::GreaterEqual => {
-            let rhs = parse_expr(tokens)?;
-            Ok(AST::GreaterEqual(Box::new(lhs), Box::new(rhs)))
-        }
-        Token::LessEqual => {
-            let rhs = parse_expr(tokens)?;
-            Ok(AST::LessEqual(Box::new(lhs), Box::new(rhs)))
-        }
-        Token::Equal => {
-            let rhs = parse_expr(tokens)?;
-            Ok(AST::Equal(Box::new(lhs), Box::new(rhs)))
-        }
-        Token::Greater => {
-            let rhs = parse_expr(tokens)?;
-            Ok(AST::Greater(Box::new(lhs), Box::new(rhs)))
-        }
-        Token::Less => {
-            let rhs = parse_expr(tokens)?;
-            Ok(AST::Less(Box::new(lhs), Box::new(rhs)))
-        }
-        Token::And => {
-            let rhs = parse_expr(tokens)?;
-            Ok(AST::And(Box::new(lhs), Box::new(rhs)))
-        }
-        Token::Or => {
-            let rhs = parse_expr(tokens)?;
-            Ok(AST::Or(Box::new(lhs), Box::new(rhs)))
+fn parse_term(tokens: &mut Tokens) -> Result<AST, ParseError> {
+    let Some(token) = tokens.next() else {
+        return Err(tokens.error("unexpected end of expression".to_string()));
+    };
+    let span = tokens.last.clone();
+
+    match token {
+        Token::Number(n) => Ok(AST::Number(n)),
+        Token::Float(f) => Ok(AST::Float(f)),
+        Token::Duration(seconds) => Ok(AST::Duration(seconds)),
+        Token::Bytes(bytes) => Ok(AST::Bytes(bytes)),
+        Token::Time(time) => Ok(AST::Time(time)),
+        Token::Timestamp(timestamp) => Ok(AST::Timestamp(timestamp)),
+        Token::Variable(name) if tokens.first() == Some(&Token::OpenParen) => {
+            tokens.next();
+            let args = parse_list(tokens, Token::CloseParen)?;
+            check_call(&name, &args)
+                .map_err(|message| ParseError::new(message, span.start..tokens.last.end))?;
+            Ok(AST::Call(name, args))
         }
-        Token::RegCompare => {
-            let rhs = parse_expr(tokens)?;
-            Ok(AST::RegCompareBinary(Box::new(lhs), Box::new(rhs)))
+        Token::Variable(v) if v == "now" => Ok(AST::Now),
+        Token::Variable(v) => Ok(AST::Variable(v)),
+        // Also a word to look for
+        Token::In => Ok(AST::Variable("in".to_string())),
+        Token::String(s) => Ok(AST::String(s)),
+        Token::OpenParen => {
+            let ast = parse_expr(tokens, OR_PRECEDENCE)?;
+            match tokens.first() {
+                Some(Token::CloseParen) => {
+                    tokens.next();
+                    Ok(ast)
+                }
+                Some(token) => {
+                    Err(tokens.error(format!("unexpected token {:?} (expected ')')", token)))
+                }
+                None => Err(tokens.error("missing ')'".to_string())),
+            }
         }
-        token => Err(format!(
-            "unexpected token {:?} (expected binary token)",
-            token
+        token => Err(ParseError::new(
+            format!("unexpected token {:?} (expected term)", token),
+            span,
         )),
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum Value {
-    Number(i64),
-    String(String),
-    Boolean(bool),
-}
-
-/**
- * Example cases, for record "this is a test=10", {"test": "10"}:
- *
- * "this" -> true
- * this -> true
- * ! "this" -> false
- * !!this -> false
- * test -> true
- * !!test -> true
- * !!nope -> false
- * test >= 10 -> false
- * 10 -> true
- * 20 -> false
- * test > 0 && test < 100 -> true
- * test > 10 || test < 100 -> true
- * test > 10 || test < 5 -> false
- * (test > 10 || test < 5) && test < 100 -> false
- */
-
-pub fn execute(ast: &AST, record: &Record) -> Value {
-    match ast {
-        AST::String(s) | AST::Variable(s) => {
-            Value::Boolean(record.original.to_lowercase().contains(&s.to_lowercase()))
-        }
-        AST::Number(n) => Value::Boolean(record.original.to_lowercase().contains(&n.to_string())),
-        _ => execute_rec(ast, record),
+/// Parses the expressions separated by commas up to `end`, as the arguments of a
+/// function or the items of a list.
+fn parse_list(tokens: &mut Tokens, end: Token) -> Result<Vec<AST>, ParseError> {
+    let mut items = Vec::new();
+    if tokens.first() == Some(&end) {
+        tokens.next();
+        return Ok(items);
     }
-}
-
-pub fn execute_rec(ast: &AST, record: &Record) -> Value {
-    match ast {
-        AST::String(s) => Value::String(s.clone()),
-        AST::Variable(var) => {
-            if let Some(value) = record.get(&var) {
-                match value.parse::<i64>() {
-                    Ok(n) => Value::Number(n),
-                    Err(_) => Value::String(value.to_string()),
-                }
-            } else {
-                Value::Boolean(false)
+    loop {
+        items.push(parse_expr(tokens, OR_PRECEDENCE)?);
+        match tokens.first() {
+            Some(Token::Comma) => {
+                tokens.next();
             }
-        }
-        AST::Number(n) => Value::Number(n.clone()),
-        AST::Boolean(b) => Value::Boolean(b.clone()),
-        AST::Equal(lhs, rhs) => {
-            let lhs = execute_rec(&lhs, record);
-            let rhs = execute_rec(&rhs, record);
-            Value::Boolean(lhs == rhs)
-        }
-        AST::Greater(lhs, rhs) => {
-            let lhs = execute_rec(&lhs, record);
-            let rhs = execute_rec(&rhs, record);
-            match (lhs, rhs) {
-                (Value::Number(lhs), Value::Number(rhs)) => Value::Boolean(lhs > rhs),
-                (Value::String(lhs), Value::String(rhs)) => Value::Boolean(lhs > rhs),
-                _ => Value::Boolean(false),
+            Some(token) if *token == end => {
+                tokens.next();
+                return Ok(items);
             }
-        }
-        AST::GreaterEqual(lhs, rhs) => {
-            let lhs = execute_rec(&lhs, record);
-            let rhs = execute_rec(&rhs, record);
-            match (lhs, rhs) {
-                (Value::Number(lhs), Value::Number(rhs)) => Value::Boolean(lhs >= rhs),
-                (Value::String(lhs), Value::String(rhs)) => Value::Boolean(lhs >= rhs),
-                _ => Value::Boolean(false),
+            Some(token) => {
+                return Err(tokens.error(format!("unexpected token {:?} (expected ',')", token)))
             }
+            None => return Err(tokens.error(format!("missing {:?}", end))),
         }
-        AST::Less(lhs, rhs) => {
-            let lhs = execute_rec(&lhs, record);
-            let rhs = execute_rec(&rhs, record);
-            match (lhs, rhs) {
-                (Value::Number(lhs), Value::Number(rhs)) => Value::Boolean(lhs < rhs),
-                (Value::String(lhs), Value::String(rhs)) => Value::Boolean(lhs < rhs),
-                _ => Value::Boolean(false),
-            }
-        }
-        AST::LessEqual(lhs, rhs) => {
-            let lhs = execute_rec(&lhs, record);
-            let rhs = execute_rec(&rhs, record);
-            match (lhs, rhs) {
-                (Value::Number(lhs), Value::Number(rhs)) => Value::Boolean(lhs <= rhs),
-                (Value::String(lhs), Value::String(rhs)) => Value::Boolean(lhs <= rhs),
-                _ => Value::Boolean(false),
-            }
-        }
-        AST::Not(ast) => {
-            if let AST::String(s) = &**ast {
-                return Value::Boolean(!record.original.to_lowercase().contains(&s.to_lowercase()));
-            }
-            let ast = execute_to_bool(ast, record);
-            match ast {
-                Value::Boolean(b) => Value::Boolean(!b),
-                _ => Value::Boolean(false),
-            }
-        }
-        AST::And(lhs, rhs) => {
-            let lhs = execute_to_bool(&lhs, record);
-            let rhs = execute_to_bool(&rhs, record);
-
-            match (lhs, rhs) {
-                (Value::Boolean(lhs), Value::Boolean(rhs)) => Value::Boolean(lhs && rhs),
-                _ => Value::Boolean(false),
-            }
-        }
-        AST::Or(lhs, rhs) => {
-            let lhs = execute_to_bool(&lhs, record);
-            let rhs = execute_to_bool(&rhs, record);
-            match (lhs, rhs) {
-                (Value::Boolean(lhs), Value::Boolean(rhs)) => Value::Boolean(lhs || rhs),
-                _ => Value::Boolean(false),
-            }
-        }
-        AST::RegCompareBinary(lhs, rhs) => {
-            let lhs = execute_rec(&lhs, record);
-            let rhs = execute_rec(&rhs, record);
-            match (lhs, rhs) {
-                (Value::String(lhs), Value::String(rhs)) => {
-                    Value::Boolean(REGEX_CACHE.matches(&rhs, &lhs))
-                }
-                _ => Value::Boolean(false),
-            }
-        }
-        AST::RegCompareUnary(ast) => match &**ast {
-            AST::String(regex) | AST::Variable(regex) => {
-                Value::Boolean(REGEX_CACHE.matches(&regex, &record.original))
-            }
-            _ => Value::Boolean(false),
-        },
-        AST::Empty => Value::Boolean(true),
     }
 }
-fn execute_to_bool(ast: &AST, record: &Record) -> Value {
-    match execute_rec(ast, record) {
-        Value::Number(_n) => Value::Boolean(true),
-        Value::String(s) => Value::Boolean(record.data.get(&s).is_some()),
-        Value::Boolean(b) => Value::Boolean(b),
+
+/// Functions of the filter language
+pub const FUNCTIONS: [&str; 6] = [
+    "contains",
+    "startswith",
+    "lower",
+    "len",
+    "exists",
+    "coalesce",
+];
+
+fn check_call(name: &str, args: &[AST]) -> Result<(), String> {
+    let expected = match name {
+        "contains" | "startswith" => 2,
+        "lower" | "len" | "exists" => 1,
+        "coalesce" if !args.is_empty() => return Ok(()),
+        "coalesce" => return Err("coalesce needs at least one argument".to_string()),
+        _ => return Err(format!("unknown function {}", name)),
+    };
+    if args.len() != expected {
+        return Err(format!("{} takes {} arguments", name, expected));
+    }
+    if name == "exists" && !matches!(args[0], AST::Variable(_)) {
+        return Err("exists takes a field name".to_string());
     }
+    Ok(())
 }
 
-impl AST {
-    pub fn matches(&self, record: &Record) -> bool {
-        match execute(self, record) {
-            Value::Boolean(b) => b,
-            _ => false,
-        }
-    }
+fn join(items: &[AST]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::filter::Filter;
     use crate::record::Record;
+    use crate::value::Value;
 
     use super::*;
 
+    fn matches(input: &str, record: &Record) -> bool {
+        Filter::new(parse(input).unwrap()).matches(record)
+    }
+
+    fn token_list(input: &str) -> Option<Vec<Token>> {
+        tokenize(input).map(|tokens| tokens.tokens)
+    }
+
     #[test]
-    fn test_tokenize() {
-        let tokens = tokenize("1 > 2");
+    fn test_token_list() {
+        let tokens = token_list("1 > 2");
         assert_eq!(
             tokens,
-            Ok(vec![Token::Number(1), Token::Greater, Token::Number(2)])
+            Some(vec![Token::Number(1), Token::Greater, Token::Number(2)])
         );
-        let tokens = tokenize("1 <= 2");
+        let tokens = token_list("1 <= 2");
         assert_eq!(
             tokens,
-            Ok(vec![Token::Number(1), Token::LessEqual, Token::Number(2)])
+            Some(vec![Token::Number(1), Token::LessEqual, Token::Number(2)])
         );
-        let tokens = tokenize("1 == 2");
+        let tokens = token_list("1 == 2");
         assert_eq!(
             tokens,
-            Ok(vec![Token::Number(1), Token::Equal, Token::Number(2)])
+            Some(vec![Token::Number(1), Token::Equal, Token::Number(2)])
         );
-        let tokens = tokenize("var >= 2");
+        let tokens = token_list("var >= 2");
         assert_eq!(
             tokens,
-            Ok(vec![
+            Some(vec![
                 Token::Variable("var".to_string()),
                 Token::GreaterEqual,
                 Token::Number(2)
             ])
         );
-        let tokens = tokenize("1 < \"string\"");
+        let tokens = token_list("1 < \"string\"");
         assert_eq!(
             tokens,
-            Ok(vec![
+            Some(vec![
                 Token::Number(1),
                 Token::Less,
                 Token::String("string".to_string())
@@ -561,6 +725,66 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tokenize_dotted_variables() {
+        let tokens = token_list("http.status >= 500");
+        assert_eq!(
+            tokens,
+            Some(vec![
+                Token::Variable("http.status".to_string()),
+                Token::GreaterEqual,
+                Token::Number(500)
+            ])
+        );
+        let tokens = token_list("tags.0 == \"a\"");
+        assert_eq!(
+            tokens,
+            Some(vec![
+                Token::Variable("tags.0".to_string()),
+                Token::Equal,
+                Token::String("a".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn test_execute_dotted_variables() {
+        let mut record = Record::new("{}".to_string());
+        record.set_data("http.status", "503".to_string());
+        record.set_data("http.headers.user_agent", "curl/8.0".to_string());
+
+        assert!(matches("http.status >= 500", &record));
+        assert!(!matches("http.status < 500", &record));
+        assert!(matches("http.headers.user_agent ~ \"^curl\"", &record));
+    }
+
+    #[test]
+    fn test_execute_typed_values() {
+        let parsers = vec![
+            crate::parser::Parser::new("logfmt").unwrap(),
+            crate::parser::Parser::new("types size=bytes took=duration ok=bool").unwrap(),
+        ];
+        let mut record = Record::new(
+            "duration=0.35 size=2KiB took=150ms ok=yes at=\"2025-01-02 10:00:00\"".to_string(),
+        );
+        record.parse(&parsers);
+
+        assert_eq!(record.get_value("size"), Some(&Value::Bytes(2048)));
+        // Untyped floats are compared as numbers, not strings
+        assert!(matches("duration > 0", &record));
+        assert!(!matches("duration >= 1", &record));
+        assert!(matches("duration == \"0.35\"", &record));
+        assert!(matches("size > 2000", &record));
+        assert!(matches("size < \"1MB\"", &record));
+        assert!(matches("took < \"1s\"", &record));
+        assert!(matches("ok == \"true\"", &record));
+
+        // Changing the data, as from lua, drops the typed value
+        record.set_data("size", "10".to_string());
+        assert_eq!(record.get_value("size"), None);
+        assert!(!matches("size > 2000", &record));
+    }
+
     #[test]
     fn test_parse() {
         assert_eq!(parse("1 >> 2").is_err(), true);
@@ -637,111 +861,332 @@ mod tests {
     }
 
     #[test]
-    fn test_one_var_is_string() {
-        assert_eq!(parse("var"), Ok(AST::String("var".into())));
-        assert_eq!(parse("\"var\""), Ok(AST::String("var".into())));
-        // assert_eq!(parse("!!var"), Ok(AST::Variable("var".into())));
-    }
-
-    #[test]
-    fn test_execute() {
-        let mut record = Record::new("2024-01-01 00:00:00".to_string());
-        record.set_data("hostname", "localhost".to_string());
-        record.set_data("program", "test".to_string());
-        record.set_data("rest", "message".to_string());
-        record.set_data("var1", "10".to_string());
-        record.set_data("var2", "20".to_string());
+    fn test_parse_precedence() {
+        let var = |name: &str| Box::new(AST::Variable(name.to_string()));
+        let number = |n: i64| Box::new(AST::Number(n));
+        // Comparisons over `&&` over `||`
         assert_eq!(
-            execute(
-                &AST::Equal(
-                    Box::new(AST::Variable("var1".to_string())),
-                    Box::new(AST::Variable("var2".to_string())),
-                ),
-                &record,
-            ),
-            Value::Boolean(false),
+            parse("a < 1 || a > 2 && b == 3"),
+            Ok(AST::Or(
+                Box::new(AST::Less(var("a"), number(1))),
+                Box::new(AST::And(
+                    Box::new(AST::Greater(var("a"), number(2))),
+                    Box::new(AST::Equal(var("b"), number(3)))
+                ))
+            ))
         );
         assert_eq!(
-            execute(
-                &AST::GreaterEqual(
-                    Box::new(AST::Variable("var1".to_string())),
-                    Box::new(AST::Variable("var2".to_string())),
-                ),
-                &record,
-            ),
-            Value::Boolean(false),
+            parse("(a || b) && c"),
+            Ok(AST::And(Box::new(AST::Or(var("a"), var("b"))), var("c")))
         );
+        // Left associative
         assert_eq!(
-            execute(
-                &AST::LessEqual(
-                    Box::new(AST::Variable("var1".to_string())),
-                    Box::new(AST::Variable("var2".to_string())),
-                ),
-                &record,
-            ),
-            Value::Boolean(true),
+            parse("a && b && c"),
+            Ok(AST::And(Box::new(AST::And(var("a"), var("b"))), var("c")))
         );
+        // `!` takes the comparison, but not the `&&`
         assert_eq!(
-            execute(&AST::Variable("var1".to_string()), &record),
-            Value::Boolean(false)
+            parse("!a == 1 && b"),
+            Ok(AST::And(
+                Box::new(AST::Not(Box::new(AST::Equal(var("a"), number(1))))),
+                var("b")
+            ))
         );
         assert_eq!(
-            execute(&AST::Variable("var2".to_string()), &record),
-            Value::Boolean(false)
+            parse("~ \"^a\" && b"),
+            Ok(AST::And(
+                Box::new(AST::RegCompareUnary(Box::new(AST::String("^a".into())))),
+                var("b")
+            ))
         );
+        assert!(parse("(a || b").is_err());
+        assert!(parse("a || b)").is_err());
+        assert!(parse("a &&").is_err());
+        assert!(parse("()").is_err());
+    }
+
+    #[test]
+    fn test_to_string_round_trip() {
+        for (input, expected) in [
+            ("a < 1 || a > 2", "a < 1 || a > 2"),
+            ("(a || b) && c", "(a || b) && c"),
+            ("a || (b && c)", "a || b && c"),
+            ("a && (b && c)", "a && (b && c)"),
+            ("!(a && b)", "!(a && b)"),
+            ("!a == 1", "!a == 1"),
+            ("(!a) == 1", "(!a) == 1"),
+            ("level == \"error\"", "level == \"error\""),
+            ("msg ~ \"say \\\"hi\\\"\"", "msg ~ \"say \\\"hi\\\"\""),
+            ("path == \"C:\\\\\"", "path == \"C:\\\\\""),
+            ("msg ~ \"\\d+\"", "msg ~ \"\\\\d+\""),
+            ("~ \"^a\"", "~\"^a\""),
+            ("text", "\"text\""),
+            ("", ""),
+        ] {
+            let ast = parse(input).unwrap();
+            assert_eq!(ast.to_string(), expected, "{}", input);
+            assert_eq!(parse(&ast.to_string()), Ok(ast), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_execute_precedence() {
+        let mut record = Record::new("line".to_string());
+        record.set_data("line_number", "1500".to_string());
+        assert!(matches("line_number > 1000 && line_number < 2000", &record));
+        assert!(!matches(
+            "line_number < 1000 || line_number > 2000",
+            &record
+        ));
+        assert!(matches(
+            "(line_number < 1000 || line_number > 1200) && line_number < 2000",
+            &record
+        ));
+    }
+
+    #[test]
+    fn test_tokenize_units() {
+        assert_eq!(token_list("0.5"), Some(vec![Token::Float(0.5)]));
+        assert_eq!(token_list("250ms"), Some(vec![Token::Duration(0.25)]));
+        assert_eq!(token_list("1h30m"), Some(vec![Token::Duration(5400.0)]));
+        assert_eq!(token_list("5m"), Some(vec![Token::Duration(300.0)]));
+        assert_eq!(token_list("2MB"), Some(vec![Token::Bytes(2_000_000)]));
+        assert_eq!(token_list("1KiB"), Some(vec![Token::Bytes(1024)]));
+        // Not numbers
         assert_eq!(
-            execute(
-                &AST::Not(Box::new(AST::Variable("var2".to_string()))),
-                &record
-            ),
-            Value::Boolean(false)
+            token_list("10.0.0.1"),
+            Some(vec![Token::Variable("10.0.0.1".to_string())])
         );
         assert_eq!(
-            execute(
-                &AST::And(
-                    Box::new(AST::Variable("var1".to_string())),
-                    Box::new(AST::Variable("var2".to_string())),
-                ),
-                &record,
-            ),
-            Value::Boolean(true),
+            token_list("2024-01"),
+            Some(vec![Token::Variable("2024-01".to_string())])
         );
         assert_eq!(
-            execute(
-                &AST::Or(
-                    Box::new(AST::Variable("var1".to_string())),
-                    Box::new(AST::Variable("var2".to_string())),
-                ),
-                &record,
-            ),
-            Value::Boolean(true),
+            token_list("99999999999999999999"),
+            Some(vec![Token::Float(1e20)])
         );
+    }
+
+    #[test]
+    fn test_execute_units() {
+        let parsers = vec![crate::parser::Parser::new("logfmt").unwrap()];
+        let mut record =
+            Record::new("latency=0.75 took=1.2s size=3MB count=2 text=250ms".to_string());
+        record.parse(&parsers);
+
+        assert!(matches("latency > 0.5", &record));
+        assert!(!matches("latency > 0.8", &record));
+        assert!(matches("count < 2.5", &record));
+        // Field values with units are converted to the unit of the literal
+        assert!(matches("took > 250ms", &record));
+        assert!(matches("took < 1m", &record));
+        assert!(matches("size > 2MB && size < 3MiB", &record));
+        assert!(matches("latency < 1s", &record));
+        // Alone, it is looked for in the text
+        assert_eq!(parse("250ms"), Ok(AST::String("250ms".to_string())));
+        assert!(matches("250ms", &record));
+
+        for input in [
+            "latency > 0.5",
+            "a == 2.0",
+            "took > 1.5s",
+            "size < 2000000B",
+        ] {
+            let ast = parse(input).unwrap();
+            assert_eq!(parse(&ast.to_string()), Ok(ast), "{}", input);
+        }
+        assert_eq!(parse("took > 250ms").unwrap().to_string(), "took > 250ms");
+        assert_eq!(parse("took > 1h30m").unwrap().to_string(), "took > 90m");
+    }
+
+    #[test]
+    fn test_time_expressions() {
+        let now = Utc::now();
+        let at = |minutes_ago: i64| (now - chrono::Duration::minutes(minutes_ago)).to_rfc3339();
+        let mut record = Record::new("recent".to_string());
+        record.set_data("timestamp", at(2));
+        let mut old = Record::new("old".to_string());
+        old.set_data("timestamp", at(60));
+
+        let last_5m = Filter::new(parse("timestamp > now - 5m").unwrap());
+        assert!(last_5m.matches(&record));
+        assert!(!last_5m.matches(&old));
+        assert!(matches("timestamp in now - 2h..now - 30m", &old));
+        assert!(matches("now - timestamp > 30m", &old));
+
+        // Times of the day compare with the time of the day of the timestamp
+        let mut record = Record::new("line".to_string());
+        record.set_data("timestamp", "2025-03-01 10:05:00".to_string());
+        assert!(matches("timestamp in 10:02..10:07", &record));
+        assert!(!matches("timestamp in 10:06..10:07", &record));
+        assert!(matches("timestamp < 10:05:30", &record));
+        assert!(matches("timestamp >= 2025-03-01T10:00:00Z", &record));
+        assert!(matches("timestamp < 2025-03-02", &record));
+        assert!(parse("n in 1..10").is_ok());
+        assert!(parse("n in 1").is_err());
+
+        for input in [
+            "timestamp > now - 5m",
+            "timestamp in 10:02:00..10:07:00",
+            "timestamp in now - 1h..now",
+            "timestamp > 2025-03-01T10:00:00Z",
+            "a - (b - c) > 1",
+        ] {
+            let ast = parse(input).unwrap();
+            assert_eq!(ast.to_string(), input);
+            assert_eq!(parse(&ast.to_string()), Ok(ast), "{}", input);
+        }
+        // Still text to look for
+        assert_eq!(parse("GET - 200"), Ok(AST::String("GET - 200".to_string())));
+        assert_eq!(parse("-v"), Ok(AST::String("-v".to_string())));
+        assert_eq!(parse("10:05"), Ok(AST::String("10:05".to_string())));
+        assert_eq!(parse("in"), Ok(AST::String("in".to_string())));
+        // Characters out of the language, as written
+        assert_eq!(token_list("a == /api"), None);
         assert_eq!(
-            execute(
-                &AST::RegCompareBinary(
-                    Box::new(AST::Variable("rest".to_string())),
-                    Box::new(AST::String(".*".to_string())),
-                ),
-                &record
-            ),
-            Value::Boolean(true),
+            parse("user@host /api"),
+            Ok(AST::String("user@host /api".to_string()))
         );
+    }
+
+    #[test]
+    fn test_functions_and_lists() {
+        let mut record = Record::new("line".to_string());
+        record.set_data("msg", "Connection Refused".to_string());
+        record.set_data("level", "error".to_string());
+        record.set_data("host", "".to_string());
+        let matches = |input: &str| matches(input, &record);
+
+        assert!(matches("contains(msg, \"Refused\")"));
+        assert!(!matches("contains(msg, \"refused\")"));
+        assert!(matches("contains(lower(msg), \"refused\")"));
+        assert!(matches("startswith(msg, \"Conn\")"));
+        assert!(!matches("startswith(missing, \"Conn\")"));
+        assert!(matches("len(msg) == 18"));
+        assert!(matches("len(host) == 0"));
+        assert!(matches("exists(host) && !exists(missing)"));
+        assert!(matches("coalesce(user, level) == \"error\""));
+        assert!(matches("coalesce(user, \"anonymous\") == \"anonymous\""));
+        assert!(matches("level in [\"error\", \"fatal\"]"));
+        assert!(!matches("level in [\"warning\"]"));
+        assert!(!matches("level in []"));
+        assert!(matches("len(msg) in [10, 18]"));
+
         assert_eq!(
-            execute(
-                &AST::RegCompareUnary(Box::new(AST::String("^2024.*".to_string())),),
-                &record
-            ),
-            Value::Boolean(true),
+            parse("level in [\"error\", \"fatal\"] || exists(x)"),
+            Ok(AST::Or(
+                Box::new(AST::InList(
+                    Box::new(AST::Variable("level".to_string())),
+                    vec![
+                        AST::String("error".to_string()),
+                        AST::String("fatal".to_string())
+                    ]
+                )),
+                Box::new(AST::Call(
+                    "exists".to_string(),
+                    vec![AST::Variable("x".to_string())]
+                ))
+            ))
         );
+        for input in [
+            "contains(lower(msg), \"x\")",
+            "level in [\"error\", \"fatal\"]",
+            "len(msg) + 1 > 10",
+            "coalesce(a, b, 1) in []",
+        ] {
+            let ast = parse(input).unwrap();
+            assert_eq!(ast.to_string(), input);
+            assert_eq!(parse(&ast.to_string()), Ok(ast), "{}", input);
+        }
+
+        assert!(parse("upper(msg)").is_err());
+        assert!(parse("contains(msg)").is_err());
+        assert!(parse("exists(\"msg\")").is_err());
+        assert!(parse("coalesce()").is_err());
+        assert!(parse("contains(msg, \"x\"").is_err());
+        assert!(parse("level in [\"error\" \"fatal\"]").is_err());
+    }
+
+    #[test]
+    fn test_parse_error_spans() {
+        let span = |input: &str| parse(input).unwrap_err().span;
+        assert_eq!(span("status >= "), 10..10);
+        assert_eq!(span("a == 1 b"), 7..8);
+        assert_eq!(span("(a == 1"), 7..7);
+        assert_eq!(span("a == )"), 5..6);
+        assert_eq!(span("x && upper(msg) == 1"), 5..15);
+        assert_eq!(span("contains(msg) && a"), 0..13);
+        assert_eq!(span("n in 1"), 6..6);
+        assert_eq!(span("level in [\"a\" \"b\"]"), 14..17);
+        assert_eq!(span("\"añb\" == )"), 10..11);
+
+        let error = parse("a == 1 b").unwrap_err();
         assert_eq!(
-            execute(
-                &AST::RegCompareUnary(Box::new(AST::String("^3024.*".to_string())),),
-                &record
-            ),
-            Value::Boolean(false),
+            error.to_string(),
+            "unexpected token Variable(\"b\") (expected operator) at column 8"
         );
     }
 
+    #[test]
+    fn test_one_var_is_string() {
+        assert_eq!(parse("var"), Ok(AST::String("var".into())));
+        assert_eq!(parse("\"var\""), Ok(AST::String("var".into())));
+        // assert_eq!(parse("!!var"), Ok(AST::Variable("var".into())));
+    }
+
+    #[test]
+    fn test_execute() {
+        let mut record = Record::new("2024-01-01 00:00:00".to_string());
+        record.set_data("hostname", "localhost".to_string());
+        record.set_data("program", "test".to_string());
+        record.set_data("rest", "message".to_string());
+        record.set_data("var1", "10".to_string());
+        record.set_data("var2", "20".to_string());
+        assert!(!Filter::new(AST::Equal(
+            Box::new(AST::Variable("var1".to_string())),
+            Box::new(AST::Variable("var2".to_string())),
+        ))
+        .matches(&record));
+        assert!(!Filter::new(AST::GreaterEqual(
+            Box::new(AST::Variable("var1".to_string())),
+            Box::new(AST::Variable("var2".to_string())),
+        ))
+        .matches(&record));
+        assert!(Filter::new(AST::LessEqual(
+            Box::new(AST::Variable("var1".to_string())),
+            Box::new(AST::Variable("var2".to_string())),
+        ))
+        .matches(&record));
+        assert!(!Filter::new(AST::Variable("var1".to_string())).matches(&record));
+        assert!(!Filter::new(AST::Variable("var2".to_string())).matches(&record));
+        assert!(
+            !Filter::new(AST::Not(Box::new(AST::Variable("var2".to_string())))).matches(&record)
+        );
+        assert!(Filter::new(AST::And(
+            Box::new(AST::Variable("var1".to_string())),
+            Box::new(AST::Variable("var2".to_string())),
+        ))
+        .matches(&record));
+        assert!(Filter::new(AST::Or(
+            Box::new(AST::Variable("var1".to_string())),
+            Box::new(AST::Variable("var2".to_string())),
+        ))
+        .matches(&record));
+        assert!(Filter::new(AST::RegCompareBinary(
+            Box::new(AST::Variable("rest".to_string())),
+            Box::new(AST::String(".*".to_string())),
+        ))
+        .matches(&record));
+        assert!(Filter::new(AST::RegCompareUnary(Box::new(AST::String(
+            "^2024.*".to_string()
+        )),))
+        .matches(&record));
+        assert!(!Filter::new(AST::RegCompareUnary(Box::new(AST::String(
+            "^3024.*".to_string()
+        )),))
+        .matches(&record));
+    }
+
     #[test]
     fn test_tokenize_parse_execute() {
         let mut record = Record::new("2024-01-01 00:00:00 text to find 123abc".to_string());
@@ -753,92 +1198,26 @@ mod tests {
         record.set_data("timestamp", "2024-01-01 00:00:00".to_string());
 
         // Empty is always true
-        assert_eq!(execute(&parse("").unwrap(), &record), Value::Boolean(true));
-        assert_eq!(
-            execute(
-                &parse("timestamp > \"2020-01-01 00:00:00").unwrap(),
-                &record
-            ),
-            Value::Boolean(true)
-        );
-        assert_eq!(
-            execute(
-                &parse("timestamp >= \"2024-01-01 00:00:00").unwrap(),
-                &record
-            ),
-            Value::Boolean(true)
-        );
-        assert_eq!(
-            execute(
-                &parse("timestamp <= \"2024-01-01 00:00:00").unwrap(),
-                &record
-            ),
-            Value::Boolean(true)
-        );
-        assert_eq!(
-            execute(
-                &parse("timestamp > \"2024-01-01 00:00:00").unwrap(),
-                &record
-            ),
-            Value::Boolean(false)
-        );
-        assert_eq!(
-            execute(
-                &parse("timestamp < \"2024-01-01 00:00:00").unwrap(),
-                &record
-            ),
-            Value::Boolean(false)
-        );
-        assert_eq!(
-            execute(&parse("2024").unwrap(), &record),
-            Value::Boolean(true)
-        );
-        assert_eq!(
-            execute(&parse("2024-01").unwrap(), &record),
-            Value::Boolean(true)
-        );
-        assert_eq!(
-            execute(&parse("123abc").unwrap(), &record),
-            Value::Boolean(true)
-        );
-        assert_eq!(
-            execute(&parse("123cba").unwrap(), &record),
-            Value::Boolean(false)
-        );
+        assert!(matches("", &record));
+        assert!(matches("timestamp > \"2020-01-01 00:00:00", &record));
+        assert!(matches("timestamp >= \"2024-01-01 00:00:00", &record));
+        assert!(matches("timestamp <= \"2024-01-01 00:00:00", &record));
+        assert!(!matches("timestamp > \"2024-01-01 00:00:00", &record));
+        assert!(!matches("timestamp < \"2024-01-01 00:00:00", &record));
+        assert!(matches("2024", &record));
+        assert!(matches("2024-01", &record));
+        assert!(matches("123abc", &record));
+        assert!(!matches("123cba", &record));
         // regex unary
-        assert_eq!(
-            execute(&parse("~ \"text.*\"").unwrap(), &record),
-            Value::Boolean(true)
-        );
+        assert!(matches("~ \"text.*\"", &record));
         // convert var to string in this context
-        assert_eq!(
-            execute(&parse("~ f..d").unwrap(), &record),
-            Value::Boolean(true)
-        );
-        assert_eq!(
-            execute(&parse("~ not_find.*").unwrap(), &record),
-            Value::Boolean(false)
-        );
-        assert_eq!(
-            execute(&parse("~ \"not_find.*\"").unwrap(), &record),
-            Value::Boolean(false)
-        );
-        assert_eq!(
-            execute(&parse("find").unwrap(), &record),
-            Value::Boolean(true)
-        );
-        assert_eq!(
-            execute(&parse("\"find").unwrap(), &record),
-            Value::Boolean(true)
-        );
-        assert_eq!(
-            execute(&parse("not_find").unwrap(), &record),
-            Value::Boolean(false)
-        );
+        assert!(matches("~ f..d", &record));
+        assert!(!matches("~ not_find.*", &record));
+        assert!(!matches("~ \"not_find.*\"", &record));
+        assert!(matches("find", &record));
+        assert!(matches("\"find", &record));
+        assert!(!matches("not_find", &record));
         // negate string
-        assert_eq!(
-            execute(&parse("! \"find\"").unwrap(), &record),
-            Value::Boolean(false)
-        );
+        assert!(!matches("! \"find\"", &record));
     }
 }
@@ -1,8 +1,9 @@
 //! Completion system for TailTales
 //!
-//! This module provides tab completion functionality for both command mode
-//! and Lua REPL mode, with clean formatting and single responsibility functions.
+//! This module provides tab completion functionality for command mode, filter
+//! mode and Lua REPL mode, with clean formatting and single responsibility functions.
 
+use crate::ast::FUNCTIONS;
 use crate::lua_engine::LuaEngine;
 use crate::state::{Mode, TuiState};
 
@@ -123,6 +124,48 @@ pub fn handle_command_completion(state: &mut TuiState, lua_engine: &mut LuaEngin
     }
 }
 
+/// Get the filter functions that start with the word before the cursor
+pub fn get_filter_completions(filter: &str, position: usize) -> (usize, Vec<String>) {
+    let position = position.min(filter.len());
+    let start = filter[..position]
+        .rfind(|c: char| !c.is_alphanumeric() && c != '_')
+        .map_or(0, |i| i + 1);
+    let word = &filter[start..position];
+    let completions = FUNCTIONS
+        .iter()
+        .filter(|function| function.starts_with(word))
+        .map(|function| format!("{}(", function))
+        .collect();
+    (start, completions)
+}
+
+/// Handle completion logic for filter mode, completing the function names
+pub fn handle_filter_completion(state: &mut TuiState) {
+    let position = state.text_edit_position.min(state.filter.len());
+    let (start, completions) = get_filter_completions(&state.filter, position);
+
+    if completions.is_empty() {
+        state.next_mode = Mode::Filter;
+        state.set_warning("No completions found".to_string());
+        return;
+    }
+
+    let common_prefix = calculate_common_prefix(&completions);
+
+    if common_prefix.len() > position - start {
+        // Auto-complete with common prefix
+        state.filter.replace_range(start..position, &common_prefix);
+        state.text_edit_position = start + common_prefix.len();
+        state.handle_filter();
+    } else {
+        // Show multiple completions in a formatted table
+        let formatted_lines = format_completions_table(&completions);
+        let display_text = formatted_lines.join("\n");
+        state.next_mode = Mode::Filter;
+        state.set_warning(display_text);
+    }
+}
+
 /// Handle completion logic for REPL mode
 pub fn handle_repl_completion(state: &mut TuiState, lua_engine: &mut LuaEngine) {
     let current = state.lua_console.input.trim();
@@ -210,6 +253,21 @@ mod tests {
         assert_eq!(calculate_common_prefix(&completions), "v");
     }
 
+    #[test]
+    fn test_get_filter_completions() {
+        let (start, completions) = get_filter_completions("level == 1 && co", 16);
+        assert_eq!(start, 14);
+        assert_eq!(completions, vec!["contains(", "coalesce("]);
+
+        let (start, completions) = get_filter_completions("len(msg) > 1", 1);
+        assert_eq!(start, 0);
+        assert_eq!(completions, vec!["lower(", "len("]);
+
+        let (_, completions) = get_filter_completions("", 0);
+        assert_eq!(completions.len(), FUNCTIONS.len());
+        assert!(get_filter_completions("x", 1).1.is_empty());
+    }
+
     #[test]
     fn test_format_completions_table() {
         // Test empty list
@@ -0,0 +1,128 @@
+//! Compressed inputs
+//!
+//! Detects gzip, zstd, xz and bzip2 files by their magic bytes, so rotated logs are
+//! read whatever their extension, and decompresses them as a stream.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+
+const MAGIC_BYTES: &[(&[u8], Compression)] = &[
+    (&[0x1f, 0x8b], Compression::Gzip),
+    (&[0x28, 0xb5, 0x2f, 0xfd], Compression::Zstd),
+    (&[0xfd, b'7', b'z', b'X', b'Z', 0x00], Compression::Xz),
+    (b"BZh", Compression::Bzip2),
+];
+
+impl Compression {
+    /// The compression of the data that starts with these bytes, if any.
+    pub fn from_magic(header: &[u8]) -> Option<Compression> {
+        MAGIC_BYTES
+            .iter()
+            .find(|(magic, _)| header.starts_with(magic))
+            .map(|(_, compression)| *compression)
+    }
+
+    /// The compression of the file, looking at its first bytes.
+    pub fn detect(filename: &str) -> io::Result<Option<Compression>> {
+        let mut header = Vec::with_capacity(6);
+        File::open(filename)?.take(6).read_to_end(&mut header)?;
+        Ok(Compression::from_magic(&header))
+    }
+
+    /// Wraps the reader with the decoder, that decompresses as it is read.
+    pub fn decoder<'a, R: Read + Send + 'a>(
+        &self,
+        reader: R,
+    ) -> io::Result<Box<dyn Read + Send + 'a>> {
+        let reader = BufReader::new(reader);
+        Ok(match self {
+            // Multi, as concatenated gzip members are still a valid gzip file
+            Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+            Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+            Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const CONTENT: &str = "first line\nsecond line\n";
+
+    fn compress(compression: Compression) -> Vec<u8> {
+        match compression {
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(CONTENT.as_bytes()).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::Zstd => zstd::encode_all(CONTENT.as_bytes(), 0).unwrap(),
+            Compression::Xz => {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(CONTENT.as_bytes()).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::Bzip2 => {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+                encoder.write_all(CONTENT.as_bytes()).unwrap();
+                encoder.finish().unwrap()
+            }
+        }
+    }
+
+    #[test]
+    fn test_detect_and_decode() {
+        for compression in [
+            Compression::Gzip,
+            Compression::Zstd,
+            Compression::Xz,
+            Compression::Bzip2,
+        ] {
+            let data = compress(compression);
+            assert_eq!(Compression::from_magic(&data), Some(compression));
+
+            let mut content = String::new();
+            compression
+                .decoder(data.as_slice())
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            assert_eq!(content, CONTENT, "{:?}", compression);
+        }
+        assert_eq!(Compression::from_magic(CONTENT.as_bytes()), None);
+        assert_eq!(Compression::from_magic(b""), None);
+    }
+
+    #[test]
+    fn test_detect_file_by_content() {
+        // No extension, it is detected by the magic bytes
+        let path =
+            std::env::temp_dir().join(format!("tailtales_decompress_{}", std::process::id()));
+        std::fs::write(&path, compress(Compression::Xz)).unwrap();
+        let filename = path.to_str().unwrap();
+        let compression = Compression::detect(filename).unwrap().unwrap();
+        assert_eq!(compression, Compression::Xz);
+
+        let mut content = String::new();
+        compression
+            .decoder(File::open(filename).unwrap())
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, CONTENT);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
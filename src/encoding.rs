@@ -0,0 +1,246 @@
+//! Input encodings
+//!
+//! Inputs are read as UTF-8, replacing the invalid bytes. Rules can declare another
+//! `encoding`, and then the input is transcoded to UTF-8 as it is read.
+
+use std::char::REPLACEMENT_CHARACTER;
+use std::io::{self, Read};
+
+use crate::parser::ParserError;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Latin1,
+    /// Little endian unless the input starts with a big endian BOM
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    pub fn new(name: &str) -> Result<Encoding, ParserError> {
+        match name.to_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            "utf-16" | "utf16" | "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+            _ => Err(ParserError::InvalidParser(format!("encoding {}", name))),
+        }
+    }
+
+    /// Wraps the reader so that it reads UTF-8.
+    pub fn reader<'a, R: Read + Send + 'a>(&self, reader: R) -> Box<dyn Read + Send + 'a> {
+        match self {
+            Encoding::Utf8 => Box::new(reader),
+            _ => Box::new(TranscodingReader::new(reader, *self)),
+        }
+    }
+
+    /// The byte order told by a UTF-16 BOM at the start of the input, and the size of
+    /// the BOM to skip.
+    pub fn with_bom(&self, start: &[u8]) -> (Encoding, usize) {
+        match (self, start) {
+            (Encoding::Utf16Le | Encoding::Utf16Be, [0xff, 0xfe, ..]) => (Encoding::Utf16Le, 2),
+            (Encoding::Utf16Le | Encoding::Utf16Be, [0xfe, 0xff, ..]) => (Encoding::Utf16Be, 2),
+            _ => (*self, 0),
+        }
+    }
+
+    /// Size in this encoding of a text read through `reader`, to know where it ends
+    /// in the file. Exact, as each U+FFFD the reader writes replaces a single UTF-16
+    /// unit, and none is written for an odd byte at the end.
+    pub fn encoded_len(&self, text: &str) -> usize {
+        match self {
+            Encoding::Utf8 => text.len(),
+            Encoding::Latin1 => text.chars().count(),
+            Encoding::Utf16Le | Encoding::Utf16Be => text.encode_utf16().count() * 2,
+        }
+    }
+}
+
+/// Transcodes from Latin-1 or UTF-16 to UTF-8. Invalid UTF-16 units are replaced by
+/// U+FFFD. An odd byte at the end is half a unit, and is dropped.
+struct TranscodingReader<R> {
+    inner: R,
+    encoding: Encoding,
+    started: bool,
+    /// Input bytes of an incomplete character, as a chunk may end in the middle of it
+    partial: Vec<u8>,
+    /// Transcoded text not read yet
+    output: Vec<u8>,
+    output_position: usize,
+}
+
+impl<R: Read> TranscodingReader<R> {
+    fn new(inner: R, encoding: Encoding) -> TranscodingReader<R> {
+        TranscodingReader {
+            inner,
+            encoding,
+            started: false,
+            partial: Vec::new(),
+            output: Vec::new(),
+            output_position: 0,
+        }
+    }
+
+    /// Transcodes the next chunk of input. Returns false at the end of the input.
+    fn fill_output(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 8192];
+        let read = self.inner.read(&mut chunk)?;
+        self.output.clear();
+        self.output_position = 0;
+        if read == 0 {
+            // A high surrogate without the low one
+            if self.partial.len() >= 2 {
+                self.push_char(REPLACEMENT_CHARACTER);
+            }
+            self.partial.clear();
+            return Ok(!self.output.is_empty());
+        }
+
+        let mut input = std::mem::take(&mut self.partial);
+        input.extend_from_slice(&chunk[..read]);
+        if !self.started && input.len() >= 2 {
+            self.started = true;
+            let (encoding, bom) = self.encoding.with_bom(&input);
+            self.encoding = encoding;
+            input.drain(..bom);
+        }
+
+        match self.encoding {
+            Encoding::Utf8 => self.output.extend_from_slice(&input),
+            Encoding::Latin1 => {
+                for byte in input {
+                    self.push_char(byte as char);
+                }
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let mut units: Vec<u16> = input
+                    .chunks_exact(2)
+                    .map(|pair| match self.encoding {
+                        Encoding::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+                        _ => u16::from_le_bytes([pair[0], pair[1]]),
+                    })
+                    .collect();
+                let mut rest = input.len() % 2;
+                // A high surrogate waits for the low one, in the next chunk
+                if units
+                    .last()
+                    .is_some_and(|unit| (0xd800..0xdc00).contains(unit))
+                {
+                    units.pop();
+                    rest += 2;
+                }
+                self.partial = input[input.len() - rest..].to_vec();
+                for c in char::decode_utf16(units) {
+                    self.push_char(c.unwrap_or(REPLACEMENT_CHARACTER));
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn push_char(&mut self, c: char) {
+        let mut buffer = [0; 4];
+        self.output
+            .extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+    }
+}
+
+impl<R: Read> Read for TranscodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.output_position >= self.output.len() {
+            if !self.fill_output()? {
+                return Ok(0);
+            }
+        }
+        let available = &self.output[self.output_position..];
+        let size = available.len().min(buf.len());
+        buf[..size].copy_from_slice(&available[..size]);
+        self.output_position += size;
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcode(encoding: Encoding, input: &[u8]) -> String {
+        let mut output = String::new();
+        encoding.reader(input).read_to_string(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_encoding_names() {
+        assert_eq!(Encoding::new("latin1").unwrap(), Encoding::Latin1);
+        assert_eq!(Encoding::new("ISO-8859-1").unwrap(), Encoding::Latin1);
+        assert_eq!(Encoding::new("utf-16").unwrap(), Encoding::Utf16Le);
+        assert_eq!(Encoding::new("UTF_16BE").unwrap(), Encoding::Utf16Be);
+        assert!(Encoding::new("ebcdic").is_err());
+    }
+
+    #[test]
+    fn test_transcode() {
+        assert_eq!(transcode(Encoding::Latin1, b"caf\xe9\n"), "café\n");
+
+        let text = "ñu 🦀\n";
+        let le: Vec<u8> = text.encode_utf16().flat_map(|u| u.to_le_bytes()).collect();
+        let be: Vec<u8> = text.encode_utf16().flat_map(|u| u.to_be_bytes()).collect();
+        assert_eq!(transcode(Encoding::Utf16Le, &le), text);
+        assert_eq!(transcode(Encoding::Utf16Be, &be), text);
+        // The BOM tells the byte order
+        let with_bom: Vec<u8> = [0xfe, 0xff].iter().chain(be.iter()).copied().collect();
+        assert_eq!(transcode(Encoding::Utf16Le, &with_bom), text);
+        // Truncated input
+        assert_eq!(
+            transcode(Encoding::Utf16Le, &le[..le.len() - 3]),
+            "ñu \u{fffd}"
+        );
+        assert_eq!(transcode(Encoding::Utf16Le, &le[..le.len() - 1]), "ñu 🦀");
+
+        assert_eq!(Encoding::Utf16Le.encoded_len(text), le.len());
+        // The same size as the invalid input that was replaced
+        for input in [&le[..le.len() - 3], &le[..le.len() - 1], &le[2..]] {
+            let output = transcode(Encoding::Utf16Le, input);
+            assert_eq!(Encoding::Utf16Le.encoded_len(&output), input.len() / 2 * 2);
+        }
+        assert_eq!(
+            Encoding::Utf16Le.with_bom(&[0xfe, 0xff, 0]),
+            (Encoding::Utf16Be, 2)
+        );
+        assert_eq!(Encoding::Utf16Le.with_bom(&le), (Encoding::Utf16Le, 0));
+        assert_eq!(
+            Encoding::Latin1.with_bom(&[0xff, 0xfe]),
+            (Encoding::Latin1, 0)
+        );
+        assert_eq!(Encoding::Latin1.encoded_len("café"), 4);
+    }
+
+    #[test]
+    fn test_transcode_split_surrogates() {
+        // One byte at a time, so characters are split across chunks
+        struct OneByte<'a>(&'a [u8]);
+        impl Read for OneByte<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                match self.0.split_first() {
+                    Some((byte, rest)) => {
+                        buf[0] = *byte;
+                        self.0 = rest;
+                        Ok(1)
+                    }
+                    None => Ok(0),
+                }
+            }
+        }
+        let text = "a🦀b";
+        let le: Vec<u8> = text.encode_utf16().flat_map(|u| u.to_le_bytes()).collect();
+        let mut output = String::new();
+        TranscodingReader::new(OneByte(&le), Encoding::Utf16Le)
+            .read_to_string(&mut output)
+            .unwrap();
+        assert_eq!(output, text);
+    }
+}
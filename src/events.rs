@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use ratatui::crossterm::event::Event;
 
 use crate::record::Record;
@@ -5,4 +7,22 @@ use crate::record::Record;
 pub enum TuiEvent {
     Key(Event),
     NewRecord(Record),
+    /// Drops the records of a source, or all of them, as the file was truncated
+    /// and is read again.
+    ClearRecords(Option<String>),
+    /// Records of a file being loaded, in batches, not parsed yet.
+    NewRecords(Vec<Record>),
+    LoadProgress(LoadProgress),
+}
+
+/// Progress of a file being loaded in the background
+#[derive(Debug, Clone)]
+pub struct LoadProgress {
+    pub filename: String,
+    /// Bytes read of the file, compressed if it is
+    pub read: u64,
+    pub total: u64,
+    pub lines: usize,
+    pub elapsed: Duration,
+    pub done: bool,
 }
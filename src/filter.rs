@@ -0,0 +1,388 @@
+//! Compiled filters
+//!
+//! Filters and searches are run on every record, maybe millions of them and from
+//! several threads. The AST is compiled once into closures, with the literals already
+//! converted to values and the regexes built, so running it does not walk the tree
+//! nor lock the shared regex cache.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
+
+use chrono::Utc;
+use regex::{Regex, RegexBuilder};
+
+use crate::ast::AST;
+use crate::record::Record;
+use crate::regex_cache::REGEX_CACHE;
+use crate::value::{self, Value};
+
+type Eval = Box<dyn Fn(&Record) -> Value + Send + Sync>;
+type Test = Box<dyn Fn(&Record) -> bool + Send + Sync>;
+type TextTest = Box<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// An AST ready to be run on records.
+#[derive(Clone)]
+pub struct Filter {
+    ast: AST,
+    test: Arc<Test>,
+}
+
+impl Filter {
+    pub fn new(ast: AST) -> Filter {
+        let test = compile_top(&ast);
+        Filter {
+            ast,
+            test: Arc::new(test),
+        }
+    }
+
+    pub fn matches(&self, record: &Record) -> bool {
+        (self.test)(record)
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::new(AST::Empty)
+    }
+}
+
+impl fmt::Debug for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Filter").field(&self.ast).finish()
+    }
+}
+
+impl PartialEq for Filter {
+    fn eq(&self, other: &Self) -> bool {
+        self.ast == other.ast
+    }
+}
+
+/// A compiled expression. Literals and fields are kept apart so they are borrowed
+/// and not cloned on every record.
+enum Operand {
+    Const(Value),
+    Field(String),
+    Eval(Eval),
+}
+
+impl Operand {
+    fn value<'a>(&'a self, record: &'a Record) -> Cow<'a, Value> {
+        match self {
+            Operand::Const(value) => Cow::Borrowed(value),
+            Operand::Field(name) => match record.get_value(name) {
+                Some(value) => Cow::Borrowed(value),
+                None => match record.get(name) {
+                    Some(text) => Cow::Owned(value::guess(text)),
+                    None => Cow::Owned(Value::Boolean(false)),
+                },
+            },
+            Operand::Eval(eval) => Cow::Owned(eval(record)),
+        }
+    }
+
+    /// As a condition: strings are true if there is a field with that name.
+    fn test(&self, record: &Record) -> bool {
+        match &*self.value(record) {
+            Value::String(s) => record.data.contains_key(s),
+            Value::Boolean(b) => *b,
+            _ => true,
+        }
+    }
+
+    /// The text, for string functions. Fields are their text as extracted, and None
+    /// if missing.
+    fn text<'a>(&'a self, record: &'a Record) -> Option<Cow<'a, str>> {
+        if let Operand::Field(name) = self {
+            return record.get(name).map(|text| Cow::Borrowed(text.as_str()));
+        }
+        Some(match self.value(record) {
+            Cow::Borrowed(Value::String(s)) => Cow::Borrowed(s.as_str()),
+            value => Cow::Owned(value_text(value.into_owned())),
+        })
+    }
+}
+
+fn value_text(value: Value) -> String {
+    match value {
+        Value::String(s) => s,
+        Value::Number(n) => n.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Timestamp(timestamp) => timestamp.to_rfc3339(),
+        Value::Time(time) => time.to_string(),
+        Value::Duration(seconds) => AST::Duration(seconds).to_string(),
+        Value::Bytes(bytes) => bytes.to_string(),
+    }
+}
+
+/// At the top, a single word or number is looked for in the whole line.
+fn compile_top(ast: &AST) -> Test {
+    match ast {
+        AST::String(s) | AST::Variable(s) => {
+            let contains = contains_ignore_case(s);
+            Box::new(move |record| contains(&record.original))
+        }
+        AST::Number(n) => {
+            let n = n.to_string();
+            Box::new(move |record| record.original.contains(&n))
+        }
+        _ => {
+            let operand = compile(ast);
+            Box::new(move |record| matches!(*operand.value(record), Value::Boolean(true)))
+        }
+    }
+}
+
+fn compile(ast: &AST) -> Operand {
+    match ast {
+        AST::String(s) => Operand::Const(Value::String(s.clone())),
+        AST::Variable(name) => Operand::Field(name.clone()),
+        AST::Number(n) => Operand::Const(Value::Number(*n)),
+        AST::Float(f) => Operand::Const(Value::Float(*f)),
+        AST::Duration(seconds) => Operand::Const(Value::Duration(*seconds)),
+        AST::Bytes(bytes) => Operand::Const(Value::Bytes(*bytes)),
+        AST::Time(time) => Operand::Const(Value::Time(*time)),
+        AST::Timestamp(timestamp) => Operand::Const(Value::Timestamp(*timestamp)),
+        AST::Now => eval(|_| Value::Timestamp(Utc::now())),
+        AST::Empty => Operand::Const(Value::Boolean(true)),
+        AST::Add(lhs, rhs) | AST::Subtract(lhs, rhs) => {
+            let subtract = matches!(ast, AST::Subtract(_, _));
+            let (lhs, rhs) = (compile(lhs), compile(rhs));
+            eval(move |record| {
+                value::add(&lhs.value(record), &rhs.value(record), subtract)
+                    .unwrap_or(Value::Boolean(false))
+            })
+        }
+        AST::Equal(lhs, rhs) => compare(lhs, rhs, |ordering| ordering == Ordering::Equal),
+        AST::Greater(lhs, rhs) => compare(lhs, rhs, |ordering| ordering == Ordering::Greater),
+        AST::GreaterEqual(lhs, rhs) => compare(lhs, rhs, |ordering| ordering != Ordering::Less),
+        AST::Less(lhs, rhs) => compare(lhs, rhs, |ordering| ordering == Ordering::Less),
+        AST::LessEqual(lhs, rhs) => compare(lhs, rhs, |ordering| ordering != Ordering::Greater),
+        AST::InRange(value, from, to) => {
+            let (value, from, to) = (compile(value), compile(from), compile(to));
+            test(move |record| {
+                let value = value.value(record);
+                value::compare(&value, &from.value(record))
+                    .is_some_and(|ordering| ordering != Ordering::Less)
+                    && value::compare(&value, &to.value(record))
+                        .is_some_and(|ordering| ordering != Ordering::Greater)
+            })
+        }
+        AST::InList(value, items) => {
+            let value = compile(value);
+            let items: Vec<Operand> = items.iter().map(compile).collect();
+            test(move |record| {
+                let value = value.value(record);
+                items.iter().any(|item| {
+                    value::compare(&value, &item.value(record)) == Some(Ordering::Equal)
+                })
+            })
+        }
+        AST::Call(name, args) => compile_call(name, args),
+        AST::Not(ast) => match &**ast {
+            AST::String(s) => {
+                let contains = contains_ignore_case(s);
+                test(move |record| !contains(&record.original))
+            }
+            ast => {
+                let operand = compile(ast);
+                test(move |record| !operand.test(record))
+            }
+        },
+        AST::And(lhs, rhs) => {
+            let (lhs, rhs) = (compile(lhs), compile(rhs));
+            test(move |record| lhs.test(record) && rhs.test(record))
+        }
+        AST::Or(lhs, rhs) => {
+            let (lhs, rhs) = (compile(lhs), compile(rhs));
+            test(move |record| lhs.test(record) || rhs.test(record))
+        }
+        AST::RegCompareBinary(lhs, rhs) => {
+            let lhs = compile(lhs);
+            match compile(rhs) {
+                Operand::Const(Value::String(pattern)) => {
+                    let regex = Regex::new(&pattern).ok();
+                    test(move |record| match (&*lhs.value(record), &regex) {
+                        (Value::String(text), Some(regex)) => regex.is_match(text),
+                        _ => false,
+                    })
+                }
+                rhs => test(
+                    move |record| match (&*lhs.value(record), &*rhs.value(record)) {
+                        (Value::String(text), Value::String(pattern)) => {
+                            REGEX_CACHE.matches(pattern, text)
+                        }
+                        _ => false,
+                    },
+                ),
+            }
+        }
+        AST::RegCompareUnary(ast) => match &**ast {
+            AST::String(pattern) | AST::Variable(pattern) => {
+                let regex = Regex::new(pattern).ok();
+                test(move |record| {
+                    regex
+                        .as_ref()
+                        .is_some_and(|regex| regex.is_match(&record.original))
+                })
+            }
+            _ => Operand::Const(Value::Boolean(false)),
+        },
+    }
+}
+
+fn compile_call(name: &str, args: &[AST]) -> Operand {
+    let mut args: Vec<Operand> = args.iter().map(compile).collect();
+    match (name, args.len()) {
+        ("contains", 2) => {
+            let (text, part) = (args.remove(0), args.remove(0));
+            test(move |record| match (text.text(record), part.text(record)) {
+                (Some(text), Some(part)) => text.contains(&*part),
+                _ => false,
+            })
+        }
+        ("startswith", 2) => {
+            let (text, start) = (args.remove(0), args.remove(0));
+            test(
+                move |record| match (text.text(record), start.text(record)) {
+                    (Some(text), Some(start)) => text.starts_with(&*start),
+                    _ => false,
+                },
+            )
+        }
+        ("lower", 1) => {
+            let text = args.remove(0);
+            eval(move |record| match text.text(record) {
+                Some(text) => Value::String(text.to_lowercase()),
+                None => Value::Boolean(false),
+            })
+        }
+        ("len", 1) => {
+            let text = args.remove(0);
+            eval(move |record| match text.text(record) {
+                Some(text) => Value::Number(text.chars().count() as i64),
+                None => Value::Boolean(false),
+            })
+        }
+        ("exists", 1) => match args.remove(0) {
+            Operand::Field(name) => test(move |record| record.get(&name).is_some()),
+            _ => Operand::Const(Value::Boolean(false)),
+        },
+        ("coalesce", _) => eval(move |record| {
+            args.iter()
+                .find(|arg| !matches!(arg, Operand::Field(name) if record.get(name).is_none()))
+                .map_or(Value::Boolean(false), |arg| arg.value(record).into_owned())
+        }),
+        _ => Operand::Const(Value::Boolean(false)),
+    }
+}
+
+fn eval<F: Fn(&Record) -> Value + Send + Sync + 'static>(f: F) -> Operand {
+    Operand::Eval(Box::new(f))
+}
+
+fn test<F: Fn(&Record) -> bool + Send + Sync + 'static>(f: F) -> Operand {
+    Operand::Eval(Box::new(move |record| Value::Boolean(f(record))))
+}
+
+fn compare<F: Fn(Ordering) -> bool + Send + Sync + 'static>(
+    lhs: &AST,
+    rhs: &AST,
+    accept: F,
+) -> Operand {
+    let (lhs, rhs) = (compile(lhs), compile(rhs));
+    test(move |record| value::compare(&lhs.value(record), &rhs.value(record)).is_some_and(&accept))
+}
+
+/// Case insensitive search of the text, without lowercasing every line.
+fn contains_ignore_case(text: &str) -> TextTest {
+    match RegexBuilder::new(&regex::escape(text))
+        .case_insensitive(true)
+        .build()
+    {
+        Ok(regex) => Box::new(move |record_text: &str| regex.is_match(record_text)),
+        Err(_) => {
+            let text = text.to_lowercase();
+            Box::new(move |record_text: &str| record_text.to_lowercase().contains(&text))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast;
+
+    #[test]
+    fn test_filters() {
+        let parsers = vec![crate::parser::Parser::new("logfmt").unwrap()];
+        let records: Vec<Record> = [
+            "level=error status=500 msg=\"Connection REFUSED\" took=250ms host=web-1",
+            "level=info status=200 msg=ok took=1.5s timestamp=2025-03-01T10:05:00Z",
+            "level=warning status=404 path=/a/b 10.0.0.1 ñandú",
+            "plain line without fields 42",
+        ]
+        .into_iter()
+        .map(|line| {
+            let mut record = Record::new(line.to_string());
+            record.parse(&parsers);
+            record
+        })
+        .collect();
+
+        for (input, expected) in [
+            ("", [true, true, true, true]),
+            ("refused", [true, false, false, false]),
+            ("ÑANDÚ", [false, false, true, false]),
+            ("42", [false, false, false, true]),
+            ("!\"error\"", [false, true, true, true]),
+            // Alone, a field value is true if there is a field named as it
+            ("level", [true, true, true, false]),
+            ("!(status < 300) && level", [false, false, false, false]),
+            ("status >= 500", [true, false, false, false]),
+            (
+                "took == \"1.5s\" || status == \"404\"",
+                [false, true, true, false],
+            ),
+            (
+                "status == 404 || level == \"info\"",
+                [false, true, true, false],
+            ),
+            ("level ~ \"err|warn\"", [true, false, true, false]),
+            ("~ \"^level=\\w+ status=2\"", [false, true, false, false]),
+            ("~ \"(\"", [false, false, false, false]),
+            ("status ~ level", [false, false, false, false]),
+            ("took > 300ms", [false, true, false, false]),
+            ("timestamp in 10:00..10:10", [false, true, false, false]),
+            ("timestamp > now - 5m", [false, false, false, false]),
+            ("status in [200, 404]", [false, true, true, false]),
+            (
+                "level in [\"error\", \"fatal\"]",
+                [true, false, false, false],
+            ),
+            (
+                "contains(lower(msg), \"refused\")",
+                [true, false, false, false],
+            ),
+            ("startswith(host, \"web\")", [true, false, false, false]),
+            ("len(msg) > 2", [true, false, false, false]),
+            ("exists(path) && !exists(host)", [false, false, true, false]),
+            (
+                "coalesce(path, host) == \"web-1\"",
+                [true, false, false, false],
+            ),
+            ("coalesce(missing, 1) == 1", [true, true, true, true]),
+        ] {
+            let filter = Filter::new(ast::parse(input).unwrap());
+            let result: Vec<bool> = records
+                .iter()
+                .map(|record| filter.matches(record))
+                .collect();
+            assert_eq!(result, expected, "{:?}", input);
+        }
+    }
+}
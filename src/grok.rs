@@ -0,0 +1,239 @@
+//! Grok named pattern library
+//!
+//! Expands `%{NAME}`, `%{NAME:field}` references into a plain regex, so that
+//! `%{IP:client} \[%{HTTPDATE:timestamp}\]` can be used instead of writing the
+//! regex by hand. The built-in library can be extended, or overriden, from the
+//! `patterns:` section of the settings.
+
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::parser::ParserError;
+use crate::value::FieldType;
+
+/// Maximum nesting of pattern references, to detect recursive definitions.
+const MAX_DEPTH: usize = 32;
+
+/// Built-in patterns, a subset of the Logstash grok library. They only use
+/// non-capturing groups so they can be referenced several times in a line.
+const BUILTIN_PATTERNS: &[(&str, &str)] = &[
+    ("USERNAME", r"[a-zA-Z0-9._-]+"),
+    ("USER", r"%{USERNAME}"),
+    ("INT", r"(?:[+-]?(?:[0-9]+))"),
+    ("BASE10NUM", r"(?:[+-]?(?:[0-9]+(?:\.[0-9]+)?|\.[0-9]+))"),
+    ("NUMBER", r"(?:%{BASE10NUM})"),
+    ("BASE16NUM", r"(?:0[xX])?[0-9A-Fa-f]+"),
+    ("POSINT", r"\b(?:[1-9][0-9]*)\b"),
+    ("NONNEGINT", r"\b(?:[0-9]+)\b"),
+    ("WORD", r"\b\w+\b"),
+    ("NOTSPACE", r"\S+"),
+    ("SPACE", r"\s*"),
+    ("DATA", r".*?"),
+    ("GREEDYDATA", r".*"),
+    ("QUOTEDSTRING", r#"(?:"(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*')"#),
+    ("QS", r"%{QUOTEDSTRING}"),
+    (
+        "UUID",
+        r"[A-Fa-f0-9]{8}-(?:[A-Fa-f0-9]{4}-){3}[A-Fa-f0-9]{12}",
+    ),
+    (
+        "IPV4",
+        r"(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)",
+    ),
+    (
+        "IPV6",
+        r"(?:[0-9A-Fa-f]{0,4}:){2,7}(?:[0-9A-Fa-f]{1,4}|%{IPV4})?(?:%\w+)?",
+    ),
+    ("IP", r"(?:%{IPV4}|%{IPV6})"),
+    (
+        "HOSTNAME",
+        r"\b(?:[0-9A-Za-z][0-9A-Za-z-]{0,62})(?:\.(?:[0-9A-Za-z][0-9A-Za-z-]{0,62}))*\.?",
+    ),
+    ("IPORHOST", r"(?:%{IP}|%{HOSTNAME})"),
+    ("HOSTPORT", r"%{IPORHOST}:%{POSINT}"),
+    ("PATH", r"(?:/[^\s]*)+"),
+    ("URIPROTO", r"[A-Za-z][A-Za-z0-9+\-.]*"),
+    ("URIPATH", r"(?:/[A-Za-z0-9$.+!*'(){},~:;=@#%&_\-]*)+"),
+    ("URIPARAM", r"\?[A-Za-z0-9$.+!*'|(){},~@#%&/=:;_?\-\[\]<>]*"),
+    ("URIPATHPARAM", r"%{URIPATH}(?:%{URIPARAM})?"),
+    ("URI", r"%{URIPROTO}://\S+"),
+    (
+        "MONTH",
+        r"\b(?:[Jj]an(?:uary)?|[Ff]eb(?:ruary)?|[Mm]ar(?:ch)?|[Aa]pr(?:il)?|[Mm]ay|[Jj]un(?:e)?|[Jj]ul(?:y)?|[Aa]ug(?:ust)?|[Ss]ep(?:tember)?|[Oo]ct(?:ober)?|[Nn]ov(?:ember)?|[Dd]ec(?:ember)?)\b",
+    ),
+    ("MONTHNUM", r"(?:0?[1-9]|1[0-2])"),
+    ("MONTHDAY", r"(?:(?:0[1-9])|(?:[12][0-9])|(?:3[01])|[1-9])"),
+    (
+        "DAY",
+        r"(?:Mon(?:day)?|Tue(?:sday)?|Wed(?:nesday)?|Thu(?:rsday)?|Fri(?:day)?|Sat(?:urday)?|Sun(?:day)?)",
+    ),
+    ("YEAR", r"(?:\d\d){1,2}"),
+    ("HOUR", r"(?:2[0123]|[01]?[0-9])"),
+    ("MINUTE", r"(?:[0-5][0-9])"),
+    ("SECOND", r"(?:(?:[0-5]?[0-9]|60)(?:[:.,][0-9]+)?)"),
+    ("TIME", r"%{HOUR}:%{MINUTE}(?::%{SECOND})?"),
+    ("ISO8601_TIMEZONE", r"(?:Z|[+-]%{HOUR}(?::?%{MINUTE}))"),
+    (
+        "TIMESTAMP_ISO8601",
+        r"%{YEAR}-%{MONTHNUM}-%{MONTHDAY}[T ]%{HOUR}:?%{MINUTE}(?::?%{SECOND})?%{ISO8601_TIMEZONE}?",
+    ),
+    ("DATE_US", r"%{MONTHNUM}[/-]%{MONTHDAY}[/-]%{YEAR}"),
+    ("DATE_EU", r"%{MONTHDAY}[./-]%{MONTHNUM}[./-]%{YEAR}"),
+    ("HTTPDATE", r"%{MONTHDAY}/%{MONTH}/%{YEAR}:%{TIME} %{INT}"),
+    ("SYSLOGTIMESTAMP", r"%{MONTH} +%{MONTHDAY} %{TIME}"),
+    (
+        "LOGLEVEL",
+        r"(?:[Aa]lert|ALERT|[Tt]race|TRACE|[Dd]ebug|DEBUG|[Nn]otice|NOTICE|[Ii]nfo|INFO|[Ww]arn?(?:ing)?|WARN?(?:ING)?|[Ee]rr?(?:or)?|ERR?(?:OR)?|[Cc]rit?(?:ical)?|CRIT?(?:ICAL)?|[Ff]atal|FATAL|[Ss]evere|SEVERE|EMERG(?:ENCY)?|[Ee]merg(?:ency)?)",
+    ),
+    (
+        "COMMONAPACHELOG",
+        r#"%{IPORHOST:clientip} %{USER:ident} %{USER:auth} \[%{HTTPDATE:timestamp}\] "(?:%{WORD:verb} %{NOTSPACE:request}(?: HTTP/%{NUMBER:httpversion})?|%{DATA:rawrequest})" %{NUMBER:response} (?:%{NUMBER:bytes}|-)"#,
+    ),
+    (
+        "COMBINEDAPACHELOG",
+        r#"%{COMMONAPACHELOG} "%{DATA:referrer}" "%{DATA:agent}""#,
+    ),
+];
+
+lazy_static::lazy_static! {
+    static ref BUILTIN_LIBRARY: HashMap<&'static str, &'static str> =
+        BUILTIN_PATTERNS.iter().cloned().collect();
+    static ref REFERENCE_RE: Regex =
+        Regex::new(r"%\{(?P<name>\w+)(?::(?P<field>[^:}]+))?(?::(?P<type>[^}]+))?\}").unwrap();
+}
+
+/// Expands all the `%{NAME}`, `%{NAME:field}` and `%{NAME:field:type}` references
+/// in the pattern. Returns the regex and the declared field types.
+///
+/// `patterns` are the user defined patterns, looked up before the built-in ones.
+pub fn expand(
+    pattern: &str,
+    patterns: &HashMap<String, String>,
+) -> Result<(String, Vec<(String, FieldType)>), ParserError> {
+    let mut types = Vec::new();
+    let expanded = expand_depth(pattern, patterns, 0, &mut types)?;
+    Ok((expanded, types))
+}
+
+fn expand_depth(
+    pattern: &str,
+    patterns: &HashMap<String, String>,
+    depth: usize,
+    types: &mut Vec<(String, FieldType)>,
+) -> Result<String, ParserError> {
+    if depth > MAX_DEPTH {
+        return Err(ParserError::InvalidParser(format!(
+            "grok recursive pattern {}",
+            pattern
+        )));
+    }
+
+    let mut result = String::new();
+    let mut last = 0;
+    for caps in REFERENCE_RE.captures_iter(pattern) {
+        let reference = caps.get(0).unwrap();
+        let name = &caps["name"];
+        let definition = match patterns.get(name) {
+            Some(definition) => definition.as_str(),
+            None => BUILTIN_LIBRARY.get(name).copied().ok_or_else(|| {
+                ParserError::InvalidParser(format!("grok unknown pattern {}", name))
+            })?,
+        };
+        let expanded = expand_depth(definition, patterns, depth + 1, types)?;
+        if let (Some(field), Some(field_type)) = (caps.name("field"), caps.name("type")) {
+            types.push((
+                field.as_str().to_string(),
+                FieldType::new(field_type.as_str())?,
+            ));
+        }
+
+        result.push_str(&pattern[last..reference.start()]);
+        match caps.name("field") {
+            Some(field) => {
+                result.push_str("(?P<");
+                result.push_str(field.as_str());
+                result.push('>');
+            }
+            None => result.push_str("(?:"),
+        }
+        result.push_str(&expanded);
+        result.push(')');
+        last = reference.end();
+    }
+    result.push_str(&pattern[last..]);
+    Ok(result)
+}
+
+/// Expands and compiles a grok pattern.
+pub fn compile(
+    pattern: &str,
+    patterns: &HashMap<String, String>,
+) -> Result<(Regex, Vec<(String, FieldType)>), ParserError> {
+    let (expanded, types) = expand(pattern, patterns)?;
+    let re = Regex::new(&expanded)
+        .map_err(|_| ParserError::InvalidParser(format!("grok {}", pattern)))?;
+    Ok((re, types))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_patterns_compile() {
+        for (name, _) in BUILTIN_PATTERNS {
+            let pattern = format!("%{{{}}}", name);
+            assert!(
+                compile(&pattern, &HashMap::new()).is_ok(),
+                "Pattern {} does not compile",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_expand_named_fields() {
+        let (re, types) = compile(
+            r"%{IP:client} \[%{HTTPDATE:timestamp}\] %{NUMBER:bytes:int}",
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(types, vec![("bytes".to_string(), FieldType::Int)]);
+        let caps = re
+            .captures("192.168.1.1 [10/Oct/2000:13:55:36 -0700] 2326")
+            .unwrap();
+        assert_eq!(&caps["client"], "192.168.1.1");
+        assert_eq!(&caps["timestamp"], "10/Oct/2000:13:55:36 -0700");
+        assert_eq!(&caps["bytes"], "2326");
+    }
+
+    #[test]
+    fn test_custom_patterns() {
+        let mut patterns = HashMap::new();
+        patterns.insert("QUEUEID".to_string(), "[0-9A-F]{10}".to_string());
+        patterns.insert(
+            "POSTFIX".to_string(),
+            "%{QUEUEID:queue}: %{GREEDYDATA:message}".to_string(),
+        );
+        // Overrides the built-in one
+        patterns.insert("WORD".to_string(), "[a-z]+".to_string());
+
+        let (re, _) = compile("%{WORD:process} %{POSTFIX}", &patterns).unwrap();
+        let caps = re
+            .captures("smtpd 3A4B5C6D7E: connect from localhost")
+            .unwrap();
+        assert_eq!(&caps["process"], "smtpd");
+        assert_eq!(&caps["queue"], "3A4B5C6D7E");
+        assert_eq!(&caps["message"], "connect from localhost");
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        assert!(compile("%{NOT_A_PATTERN:x}", &HashMap::new()).is_err());
+
+        let mut patterns = HashMap::new();
+        patterns.insert("LOOP".to_string(), "a%{LOOP}".to_string());
+        assert!(compile("%{LOOP}", &patterns).is_err());
+    }
+}
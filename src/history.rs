@@ -0,0 +1,144 @@
+//! History of the filters and searches
+//!
+//! The entered texts are recalled with up and down, as in the Lua REPL. They are kept
+//! one per line in a file of the XDG config dir, so they survive restarts.
+
+use std::path::PathBuf;
+
+/// Entries kept, the oldest are dropped.
+const MAX_ENTRIES: usize = 100;
+
+#[derive(Debug, Default, Clone)]
+pub struct History {
+    pub entries: Vec<String>,
+    /// Entry being shown while navigating, None while writing a new one
+    index: Option<usize>,
+    /// What was being written before navigating
+    current: String,
+    /// File where it is kept, if any
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// The history kept at `~/.config/tailtales/<name>`.
+    pub fn load(name: &str) -> History {
+        match xdg::BaseDirectories::with_prefix("tailtales")
+            .ok()
+            .and_then(|xdg| xdg.place_config_file(name).ok())
+        {
+            Some(path) => History::from_file(path),
+            None => History::default(),
+        }
+    }
+
+    pub fn from_file(path: PathBuf) -> History {
+        let entries = std::fs::read_to_string(&path)
+            .map(|contents| {
+                contents
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(|line| line.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        History {
+            entries,
+            path: Some(path),
+            ..History::default()
+        }
+    }
+
+    /// Adds an entry, moving it to the end if already there, and saves the history.
+    pub fn add(&mut self, entry: &str) {
+        self.reset_navigation();
+        let entry = entry.trim();
+        if entry.is_empty() || entry.contains('\n') {
+            return;
+        }
+        self.entries.retain(|e| e != entry);
+        self.entries.push(entry.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.drain(..self.entries.len() - MAX_ENTRIES);
+        }
+
+        if let Some(path) = &self.path {
+            let mut contents = self.entries.join("\n");
+            contents.push('\n');
+            if let Err(err) = std::fs::write(path, contents) {
+                log::warn!("Could not save history to {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Shows the previous entry in `input`. False if there are no older ones.
+    pub fn up(&mut self, input: &mut String) -> bool {
+        let index = match self.index {
+            None if !self.entries.is_empty() => {
+                self.current = input.clone();
+                self.entries.len() - 1
+            }
+            Some(index) if index > 0 => index - 1,
+            _ => return false,
+        };
+        self.index = Some(index);
+        *input = self.entries[index].clone();
+        true
+    }
+
+    /// Shows the next entry in `input`, or what was being written after the last one.
+    pub fn down(&mut self, input: &mut String) -> bool {
+        match self.index {
+            None => false,
+            Some(index) if index + 1 < self.entries.len() => {
+                self.index = Some(index + 1);
+                *input = self.entries[index + 1].clone();
+                true
+            }
+            Some(_) => {
+                *input = std::mem::take(&mut self.current);
+                self.index = None;
+                true
+            }
+        }
+    }
+
+    pub fn reset_navigation(&mut self) {
+        self.index = None;
+        self.current.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let path = std::env::temp_dir().join(format!("tailtales_history_{}", std::process::id()));
+        let mut history = History::from_file(path.clone());
+        assert!(history.entries.is_empty());
+        let mut input = "new".to_string();
+        assert!(!history.up(&mut input));
+
+        history.add("a == 1");
+        history.add("b == 2");
+        history.add("  ");
+        history.add("a == 1");
+        assert_eq!(history.entries, vec!["b == 2", "a == 1"]);
+
+        assert!(history.up(&mut input));
+        assert_eq!(input, "a == 1");
+        assert!(history.up(&mut input));
+        assert_eq!(input, "b == 2");
+        assert!(!history.up(&mut input));
+        assert!(history.down(&mut input));
+        assert!(history.down(&mut input));
+        assert_eq!(input, "new");
+        assert!(!history.down(&mut input));
+
+        // Kept in the file
+        let history = History::from_file(path.clone());
+        assert_eq!(history.entries, vec!["b == 2", "a == 1"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -5,7 +5,8 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::{
     ast,
-    completions::{handle_command_completion, handle_repl_completion},
+    completions::{handle_command_completion, handle_filter_completion, handle_repl_completion},
+    filter::Filter,
     lua_console::ConsoleLine,
     lua_engine::LuaEngine,
     settings::Settings,
@@ -127,9 +128,11 @@ pub fn handle_search_mode(key_event: KeyEvent, state: &mut TuiState) {
     match key_event.code {
         KeyCode::Esc => {
             state.mode = Mode::Normal;
+            state.search_history.reset_navigation();
         }
         KeyCode::Char('\n') => {
             state.mode = Mode::Normal;
+            state.search_history.add(&state.search);
             state.search_fwd();
         }
         KeyCode::Backspace => {
@@ -137,14 +140,17 @@ pub fn handle_search_mode(key_event: KeyEvent, state: &mut TuiState) {
         }
         KeyCode::Enter => {
             state.mode = Mode::Normal;
+            state.search_history.add(&state.search);
             state.search_fwd();
         }
+        KeyCode::Up => state.search_history_up(),
+        KeyCode::Down => state.search_history_down(),
         KeyCode::F(3) => {
             state.search_next();
         }
         _ => {
             handle_textinput(&mut state.search, &mut state.text_edit_position, key_event);
-            state.search_ast = ast::parse(&state.search).ok();
+            state.search_filter = ast::parse(&state.search).ok().map(Filter::new);
             state.search_fwd();
         }
     }
@@ -278,16 +284,22 @@ pub fn handle_filter_mode(key_event: KeyEvent, state: &mut TuiState) {
         KeyCode::Esc => {
             state.mode = Mode::Normal;
             state.filter = String::new();
+            state.filter_history.reset_navigation();
             state.handle_filter()
         }
         KeyCode::Char('\n') => {
             state.mode = Mode::Normal;
+            state.filter_history.add(&state.filter);
             state.handle_filter()
         }
         KeyCode::Enter => {
             state.mode = Mode::Normal;
+            state.filter_history.add(&state.filter);
             state.handle_filter()
         }
+        KeyCode::Up => state.filter_history_up(),
+        KeyCode::Down => state.filter_history_down(),
+        KeyCode::Tab => handle_filter_completion(state),
         _ => {
             handle_textinput(&mut state.filter, &mut state.text_edit_position, key_event);
             state.handle_filter();
@@ -0,0 +1,391 @@
+//! Lazy, memory mapped files
+//!
+//! For files too big to be read in memory, the file is mapped and only the offset
+//! of each line is kept. Records are parsed when they are shown, filtered or
+//! searched, and only the ones being shown, or changed (marks), are kept.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use memmap2::Mmap;
+use rayon::prelude::*;
+
+use crate::{filter::Filter, parser::Parser, record::Record};
+
+/// Bytes per chunk when looking for the line ends in parallel.
+const INDEX_CHUNK_SIZE: usize = 1 << 20;
+/// Parsed records kept, besides the ones being shown and the pinned ones.
+const CACHE_SIZE: usize = 10_000;
+
+#[derive(Debug)]
+pub struct LazyFile {
+    pub filename: String,
+    /// The mapped file, to check its length before reading the map
+    file: std::fs::File,
+    mmap: Mmap,
+    /// Start of each line in the map, and the end of the last one.
+    offsets: Vec<usize>,
+    /// Records of the lines added after the file was mapped, as it is followed.
+    appended: Vec<Record>,
+    /// Line numbers (0-based) that pass the filter, or None if all of them.
+    visible: Option<Vec<usize>>,
+    /// Parsed records, by line number.
+    cache: HashMap<usize, Record>,
+    /// Lines changed since parsed. They are never evicted from the cache.
+    pinned: HashSet<usize>,
+}
+
+impl LazyFile {
+    pub fn open(filename: &str) -> std::io::Result<LazyFile> {
+        let file = std::fs::File::open(filename)?;
+        // SAFETY: the map is only read, but it is not a copy. If the file is truncated
+        // while mapped, reading past its new end raises SIGBUS and kills the process.
+        // The length of the file is checked once before each parse, filter or search,
+        // and followed lazy files are dropped on truncation, but a truncation during one
+        // of them still crashes. Lazy mode is not for files that may be truncated in place.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let offsets = line_offsets(&mmap);
+
+        Ok(LazyFile {
+            filename: filename.to_string(),
+            file,
+            mmap,
+            offsets,
+            appended: Vec::new(),
+            visible: None,
+            cache: HashMap::new(),
+            pinned: HashSet::new(),
+        })
+    }
+
+    /// Size of the mapped part of the file, where following starts.
+    pub fn mapped_len(&self) -> usize {
+        self.mmap.len()
+    }
+
+    fn mapped_lines(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    /// Number of lines, mapped and appended.
+    pub fn line_count(&self) -> usize {
+        self.mapped_lines() + self.appended.len()
+    }
+
+    /// Number of visible lines.
+    pub fn len(&self) -> usize {
+        match &self.visible {
+            Some(visible) => visible.len(),
+            None => self.line_count(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn max_line_len(&self) -> usize {
+        self.offsets
+            .windows(2)
+            .map(|line| line[1] - line[0])
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn line_at(&self, index: usize) -> Option<usize> {
+        match &self.visible {
+            Some(visible) => visible.get(index).copied(),
+            None if index < self.line_count() => Some(index),
+            None => None,
+        }
+    }
+
+    /// Current length of the file, read once for all the lines read in a pass.
+    fn file_len(&self) -> u64 {
+        self.file.metadata().map_or(0, |metadata| metadata.len())
+    }
+
+    /// Bytes of a mapped line, without the line end. Empty if the file is now shorter
+    /// than `file_len`, as reading past its end would raise SIGBUS.
+    fn line(&self, line: usize, file_len: u64) -> &[u8] {
+        let (start, end) = (self.offsets[line], self.offsets[line + 1]);
+        if end as u64 > file_len {
+            return &[];
+        }
+        let mut text = &self.mmap[start..end];
+        if let Some(rest) = text.strip_suffix(b"\n") {
+            text = rest.strip_suffix(b"\r").unwrap_or(rest);
+        }
+        text
+    }
+
+    /// Parses a mapped line. The cache is not updated.
+    fn parse_line(&self, line: usize, parsers: &Vec<Parser>, file_len: u64) -> Record {
+        let mut record = Record::from_bytes(self.line(line, file_len));
+        record.set_data("filename", self.filename.clone());
+        record.set_source_position(Some(line + 1), self.offsets[line] as u64);
+        record.parse(parsers);
+        record
+    }
+
+    fn matches(&self, line: usize, search: &Filter, parsers: &Vec<Parser>, file_len: u64) -> bool {
+        match self.get_line(line) {
+            Some(record) => search.matches(record),
+            None => search.matches(&self.parse_line(line, parsers, file_len)),
+        }
+    }
+
+    fn get_line(&self, line: usize) -> Option<&Record> {
+        if line >= self.mapped_lines() {
+            self.appended.get(line - self.mapped_lines())
+        } else {
+            self.cache.get(&line)
+        }
+    }
+
+    /// The record at a visible position, if already parsed.
+    pub fn get(&self, index: usize) -> Option<&Record> {
+        self.line_at(index).and_then(|line| self.get_line(line))
+    }
+
+    /// The record at a visible position, parsed if needed. It is pinned as it may be
+    /// changed, so it is not parsed again and the changes lost.
+    pub fn get_mut(&mut self, index: usize, parsers: &Vec<Parser>) -> Option<&mut Record> {
+        let line = self.line_at(index)?;
+        if line >= self.mapped_lines() {
+            let mapped_lines = self.mapped_lines();
+            return self.appended.get_mut(line - mapped_lines);
+        }
+        self.materialize(index..index + 1, parsers);
+        self.pinned.insert(line);
+        self.cache.get_mut(&line)
+    }
+
+    /// Parses the records at the visible positions in the range, so they can be
+    /// shown, and evicts the ones far from it.
+    pub fn materialize(&mut self, range: Range<usize>, parsers: &Vec<Parser>) {
+        if self.is_empty() {
+            return;
+        }
+        let range = range.start.min(self.len())..range.end.min(self.len());
+        let lines: Vec<(usize, usize)> = range
+            .filter_map(|index| self.line_at(index).map(|line| (index, line)))
+            .collect();
+
+        if self.cache.len() > CACHE_SIZE {
+            let keep: HashSet<usize> = lines.iter().map(|(_, line)| *line).collect();
+            let pinned = &self.pinned;
+            self.cache
+                .retain(|line, _| pinned.contains(line) || keep.contains(line));
+        }
+
+        let file_len = self.file_len();
+        let parsed: Vec<(usize, Record)> = lines
+            .par_iter()
+            .filter(|(_, line)| *line < self.mapped_lines() && !self.cache.contains_key(line))
+            .map(|(_, line)| (*line, self.parse_line(*line, parsers, file_len)))
+            .collect();
+        self.cache.extend(parsed);
+
+        for (index, line) in lines {
+            let record = if line >= self.mapped_lines() {
+                let mapped_lines = self.mapped_lines();
+                self.appended.get_mut(line - mapped_lines)
+            } else {
+                self.cache.get_mut(&line)
+            };
+            if let Some(record) = record {
+                record.index = index;
+            }
+        }
+    }
+
+    /// Keeps visible only the lines that match, parsing all of them in parallel.
+    pub fn filter(&mut self, search: &Filter, parsers: &Vec<Parser>) {
+        let file_len = self.file_len();
+        let visible = (0..self.line_count())
+            .into_par_iter()
+            .filter(|line| self.matches(*line, search, parsers, file_len))
+            .collect();
+        self.visible = Some(visible);
+    }
+
+    /// Visible position of the first match from `start_at`.
+    pub fn search_forward(
+        &self,
+        search: &Filter,
+        start_at: usize,
+        parsers: &Vec<Parser>,
+    ) -> Option<usize> {
+        let file_len = self.file_len();
+        (start_at..self.len()).into_par_iter().find_first(|index| {
+            self.line_at(*index)
+                .is_some_and(|line| self.matches(line, search, parsers, file_len))
+        })
+    }
+
+    /// Visible position of the last match up to `end_at`, included.
+    pub fn search_backwards(
+        &self,
+        search: &Filter,
+        end_at: usize,
+        parsers: &Vec<Parser>,
+    ) -> Option<usize> {
+        let file_len = self.file_len();
+        (0..(end_at + 1).min(self.len()))
+            .into_par_iter()
+            .find_last(|index| {
+                self.line_at(*index)
+                    .is_some_and(|line| self.matches(line, search, parsers, file_len))
+            })
+    }
+
+    /// Adds a line read after the file was mapped.
+    pub fn push(&mut self, record: Record, visible: bool) {
+        let line = self.line_count();
+        self.appended.push(record);
+        if let (Some(lines), true) = (self.visible.as_mut(), visible) {
+            lines.push(line);
+        }
+    }
+
+    /// Parses again the changed and appended records. The rest are dropped and parsed
+    /// again when needed.
+    pub fn reparse(&mut self, parsers: &Vec<Parser>) {
+        let pinned = &self.pinned;
+        self.cache.retain(|line, _| pinned.contains(line));
+        self.cache
+            .par_iter_mut()
+            .for_each(|(_, record)| record.parse(parsers));
+        self.appended
+            .par_iter_mut()
+            .for_each(|record| record.parse(parsers));
+    }
+}
+
+/// Start of each line, and the end of the last one.
+fn line_offsets(data: &[u8]) -> Vec<usize> {
+    let ends: Vec<Vec<usize>> = data
+        .par_chunks(INDEX_CHUNK_SIZE)
+        .enumerate()
+        .map(|(chunk, bytes)| {
+            bytes
+                .iter()
+                .enumerate()
+                .filter(|(_, byte)| **byte == b'\n')
+                .map(|(i, _)| chunk * INDEX_CHUNK_SIZE + i + 1)
+                .collect()
+        })
+        .collect();
+
+    let mut offsets = Vec::with_capacity(ends.iter().map(|ends| ends.len()).sum::<usize>() + 2);
+    offsets.push(0);
+    offsets.extend(ends.into_iter().flatten());
+    if *offsets.last().unwrap() != data.len() {
+        // Last line without line end
+        offsets.push(data.len());
+    }
+    offsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn lazy_file(name: &str, content: &str) -> LazyFile {
+        let path =
+            std::env::temp_dir().join(format!("tailtales_lazy_{}_{}", std::process::id(), name));
+        std::fs::File::create(&path)
+            .unwrap()
+            .write_all(content.as_bytes())
+            .unwrap();
+        let lazy = LazyFile::open(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        lazy
+    }
+
+    fn filter(input: &str) -> Filter {
+        Filter::new(crate::ast::parse(input).unwrap())
+    }
+
+    #[test]
+    fn test_line_offsets() {
+        assert_eq!(line_offsets(b""), vec![0]);
+        assert_eq!(line_offsets(b"a\nbc\n"), vec![0, 2, 5]);
+        assert_eq!(line_offsets(b"a\nbc"), vec![0, 2, 4]);
+        assert_eq!(line_offsets(b"\n\n"), vec![0, 1, 2]);
+
+        let lazy = lazy_file("offsets.log", "first\r\nsecond\nthird");
+        let file_len = lazy.file_len();
+        assert_eq!(lazy.line_count(), 3);
+        assert_eq!(lazy.line(0, file_len), b"first");
+        assert_eq!(lazy.line(1, file_len), b"second");
+        assert_eq!(lazy.line(2, file_len), b"third");
+        assert_eq!(lazy.max_line_len(), 7);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let path =
+            std::env::temp_dir().join(format!("tailtales_lazy_{}_truncated", std::process::id()));
+        std::fs::write(&path, "first\nsecond\n").unwrap();
+        let lazy = LazyFile::open(path.to_str().unwrap()).unwrap();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(8)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // Not read past the new end
+        let file_len = lazy.file_len();
+        assert_eq!(file_len, 8);
+        assert_eq!(lazy.line(0, file_len), b"first");
+        assert_eq!(lazy.line(1, file_len), b"");
+    }
+
+    #[test]
+    fn test_materialize_filter_and_pin() {
+        let content: String = (1..=100).map(|i| format!("n={}\n", i)).collect();
+        let mut lazy = lazy_file("filter.log", &content);
+        let parsers = vec![Parser::new("logfmt").unwrap()];
+
+        assert_eq!(lazy.len(), 100);
+        assert!(lazy.get(10).is_none());
+        lazy.materialize(10..12, &parsers);
+        let record = lazy.get(10).unwrap();
+        assert_eq!(record.get("n").unwrap(), "11");
+        assert_eq!(record.get("line_number").unwrap(), "11");
+        assert_eq!(record.byte_offset, Some(41));
+        assert_eq!(record.index, 10);
+        assert!(lazy.get(12).is_none());
+
+        lazy.get_mut(20, &parsers)
+            .unwrap()
+            .set_data("mark", "red".into());
+
+        lazy.filter(&filter("n > 90"), &parsers);
+        assert_eq!(lazy.len(), 10);
+        lazy.materialize(0..1, &parsers);
+        assert_eq!(lazy.get(0).unwrap().get("n").unwrap(), "91");
+        assert_eq!(lazy.get(0).unwrap().index, 0);
+
+        let search = filter("n == 95");
+        assert_eq!(lazy.search_forward(&search, 0, &parsers), Some(4));
+        assert_eq!(lazy.search_backwards(&search, 3, &parsers), None);
+
+        // Marks survive the filter and the reparse, as the record is pinned
+        lazy.filter(&filter("mark == \"red\""), &parsers);
+        lazy.reparse(&parsers);
+        assert_eq!(lazy.len(), 1);
+        assert_eq!(lazy.get(0).unwrap().get("n").unwrap(), "21");
+
+        let mut record = Record::new("n=101".into());
+        record.set_data("mark", "blue".into());
+        lazy.push(record, true);
+        assert_eq!(lazy.len(), 2);
+        assert_eq!(lazy.line_count(), 101);
+        assert_eq!(lazy.get(1).unwrap().original, "n=101");
+    }
+}
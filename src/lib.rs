@@ -7,11 +7,18 @@ pub mod application;
 pub mod args;
 pub mod ast;
 pub mod completions;
+pub mod decompress;
+pub mod encoding;
 pub mod events;
+pub mod filter;
+pub mod grok;
+pub mod history;
 pub mod keyboard_input;
 pub mod keyboard_management;
+pub mod lazyfile;
 pub mod lua_console;
 pub mod lua_engine;
+pub mod multiline;
 pub mod parser;
 pub mod record;
 pub mod recordlist;
@@ -20,6 +27,7 @@ pub mod settings;
 pub mod state;
 pub mod tuichrome;
 pub mod utils;
+pub mod value;
 
 // Re-export commonly used types for convenience
 pub use lua_engine::{LuaEngine, LuaEngineError};
@@ -602,6 +602,21 @@ impl LuaEngine {
             Ok(())
         })?;
 
+        self.register_function(
+            "follow",
+            |lua, following: Option<bool>| -> LuaResult<bool> {
+                let state = Self::get_state_from_registry(lua)?;
+                state.set_following(following.unwrap_or(!state.following));
+                Ok(state.following)
+            },
+        )?;
+
+        self.register_function("toggle_binary_display", |lua, ()| -> LuaResult<()> {
+            let state = Self::get_state_from_registry(lua)?;
+            state.toggle_binary_display();
+            Ok(())
+        })?;
+
         self.register_function("lua_repl", |lua, ()| -> LuaResult<()> {
             let state = Self::get_state_from_registry(lua)?;
             state.set_mode("lua_repl");
@@ -646,7 +661,7 @@ impl LuaEngine {
             // Apply the filter
             state.records.filter_parallel(parsed);
             state.set_position(1); // Reset to first record
-            state.filter_ok = true;
+            state.filter_error = None;
 
             // Update the filter string in state for consistency
             state.filter = expression;
@@ -655,6 +670,20 @@ impl LuaEngine {
             Ok(state.records.len())
         })?;
 
+        // Save the current filter with a name in the current rule
+        self.register_function("save_filter", |lua, name: String| -> LuaResult<()> {
+            let state = Self::get_state_from_registry(lua)?;
+            state.save_filter(&name);
+            Ok(())
+        })?;
+
+        // Apply a named filter of the current rule
+        self.register_function("load_filter", |lua, name: String| -> LuaResult<()> {
+            let state = Self::get_state_from_registry(lua)?;
+            state.load_filter(&name);
+            Ok(())
+        })?;
+
         // Update record attribute (add, update, or remove if nil)
         self.register_function(
             "update_record_attribute",
@@ -763,6 +792,7 @@ impl LuaEngine {
 
                 // Get record at specified index, or current position if none provided
                 let position = index.unwrap_or(state.position - 1); // Convert to 0-based for array access
+                state.records.materialize(position..position + 1);
 
                 if let Some(record) = state.records.get(position) {
                     let record_table = lua.create_table()?;
@@ -833,6 +863,7 @@ impl LuaEngine {
 
                 // Get record at specified index, or current position if none provided
                 let position = index.unwrap_or(state.position - 1); // Convert to 0-based for array access
+                state.records.materialize(position..position + 1);
 
                 if let Some(record) = state.records.get(position) {
                     let record_table = lua.create_table()?;
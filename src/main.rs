@@ -3,24 +3,34 @@ use std::io::{self, IsTerminal};
 use std::time::{self};
 
 use application::Application;
+use chrono::{DateTime, NaiveDate, Utc};
 use parser::Parser;
 use regex::Regex;
 use settings::Settings;
 use settings::{Alignment, RulesSettings};
 
 use crate::args::{parse_args_with_clap, ParsedArgs};
-use crate::recordlist::load_parsers;
+use crate::decompress::Compression;
+use crate::encoding::Encoding;
+use crate::recordlist::{load_encoding, load_multiline, load_parsers, SourceRule, Tail};
 use std::fs;
 
 mod application;
 mod args;
 mod ast;
 mod completions;
+mod decompress;
+mod encoding;
 mod events;
+mod filter;
+mod grok;
+mod history;
 mod keyboard_input;
 mod keyboard_management;
+mod lazyfile;
 mod lua_console;
 mod lua_engine;
+mod multiline;
 mod parser;
 mod record;
 mod recordlist;
@@ -29,6 +39,7 @@ mod settings;
 mod state;
 mod tuichrome;
 mod utils;
+mod value;
 
 fn main() {
     // Get command line arguments
@@ -85,6 +96,7 @@ fn apply_args_to_app(args: ParsedArgs, app: &mut Application) {
     }
 
     // Handle mode selection
+    let explicit_rule = args.rule.is_some();
     if let Some(rule) = args.rule {
         set_rule_by_name(&rule, app);
     } else {
@@ -93,11 +105,38 @@ fn apply_args_to_app(args: ParsedArgs, app: &mut Application) {
             let mut args_vec = vec!["tt".to_string()]; // Program name
             args_vec.extend(args.files.clone());
             args_vec
+        } else if !args.listen.is_empty() {
+            // The rule is guessed from the address, as `udp://...`
+            vec!["tt".to_string(), args.listen[0].clone()]
         } else {
             vec!["tt".to_string()]
         };
         set_rule_from_args(&args_vec, app);
     }
+    if let Some(max_records) = args.max_records.or(app.state.current_rule.max_records) {
+        app.state.records.max_records = Some(max_records);
+    }
+
+    for address in &args.listen {
+        if let Err(err) = app.state.records.listen(address, app.ui.tx.clone()) {
+            eprintln!("Error: Could not listen at {}: {}", address, err);
+            std::process::exit(1);
+        }
+    }
+    if !args.listen.is_empty() && args.files.is_empty() {
+        return;
+    }
+
+    let tail = tail_option(args.lines, args.bytes.as_deref(), app);
+    let (since, until) = time_range(args.since.as_deref(), args.until.as_deref());
+    // What reads only part of the files, the time range first
+    let window = if since.is_some() || until.is_some() {
+        Some("--since and --until")
+    } else if tail.is_some() {
+        Some("--lines and --bytes")
+    } else {
+        None
+    };
 
     // Handle file processing
     let args_vec = if !args.files.is_empty() {
@@ -117,6 +156,19 @@ fn apply_args_to_app(args: ParsedArgs, app: &mut Application) {
         return;
     }
 
+    // Several files are merged in a single timeline
+    let filenames = &args_vec[1..];
+    if filenames.len() > 1
+        && filenames
+            .iter()
+            .all(|filename| filename != "-" && filename != "--" && !filename.starts_with("!"))
+    {
+        let read_whole = read_merged_files(filenames, explicit_rule, since, until, tail, app);
+        warn_read_whole(window, &read_whole, app);
+        return;
+    }
+
+    let mut read_whole = Vec::new();
     let mut narg = 1;
     while narg < args_vec.len() {
         let filename = &args_vec[narg];
@@ -135,15 +187,33 @@ fn apply_args_to_app(args: ParsedArgs, app: &mut Application) {
             }
             app.state.records.readfile_exec(&args, app.ui.tx.clone());
             return;
-        } else if filename.ends_with(".gz") {
-            app.state.records.readfile_gz(&filename);
+        } else if let Ok(Some(compression)) = Compression::detect(filename) {
+            read_whole.push(filename.clone());
+            app.state
+                .records
+                .readfile_compressed(filename, compression, app.ui.tx.clone());
+        } else if since.is_some() || until.is_some() {
+            if app.state.records.encoding != Encoding::Utf8 {
+                read_whole.push(filename.clone());
+            }
+            app.state
+                .records
+                .readfile_time_range(&filename, since, until, app.ui.tx.clone());
+        } else if tail.is_none() && use_lazy(filename, args.lazy, app) {
+            app.state
+                .records
+                .readfile_lazy(&filename, app.ui.tx.clone());
         } else {
+            if app.state.records.encoding != Encoding::Utf8 {
+                read_whole.push(filename.clone());
+            }
             app.state
                 .records
-                .readfile_parallel(&filename, app.ui.tx.clone());
+                .readfile_parallel(&filename, tail, app.ui.tx.clone());
         }
         narg += 1;
     }
+    warn_read_whole(window, &read_whole, app);
 
     // If the parser is CSV, we auto add the columns from the headers
     for parser_i in &app.state.records.parsers {
@@ -163,6 +233,132 @@ fn apply_args_to_app(args: ParsedArgs, app: &mut Application) {
     }
 }
 
+/// The part of the files to read, from the command line or else the settings.
+fn tail_option(lines: Option<usize>, bytes: Option<&str>, app: &Application) -> Option<Tail> {
+    let global = &app.state.settings.global;
+    let (lines, bytes) = match (lines, bytes) {
+        (None, None) => (global.tail_lines, global.tail_bytes.as_deref()),
+        given => given,
+    };
+    if let Some(lines) = lines {
+        return Some(Tail::Lines(lines));
+    }
+    let bytes = bytes?;
+    match value::parse_bytes(bytes) {
+        Some(bytes) => Some(Tail::Bytes(bytes)),
+        None => {
+            eprintln!("Error: Invalid size {:?}", bytes);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The `--since` and `--until` times. Times of the day are on the `--since` day, or today.
+fn time_range(
+    since: Option<&str>,
+    until: Option<&str>,
+) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+    let parse = |time: &str, date: NaiveDate| match value::parse_time_bound(time, date) {
+        Some(time) => time,
+        None => {
+            eprintln!("Error: Invalid time {:?}", time);
+            std::process::exit(1);
+        }
+    };
+    let today = Utc::now().date_naive();
+    let since = since.map(|since| parse(since, today));
+    let date = since.map_or(today, |since| since.date_naive());
+    let until = until.map(|until| parse(until, date));
+    (since, until)
+}
+
+/// Whether to map the file, if asked for or bigger than the `lazy_threshold` setting.
+/// Lines can not be joined without reading them, so never with multiline rules, and
+/// maps are only indexed as UTF-8.
+fn use_lazy(filename: &str, lazy: bool, app: &Application) -> bool {
+    if app.state.records.multiline.is_some() || app.state.records.encoding != Encoding::Utf8 {
+        return false;
+    }
+    if lazy {
+        return true;
+    }
+    let threshold = app
+        .state
+        .settings
+        .global
+        .lazy_threshold
+        .as_deref()
+        .and_then(value::parse_bytes);
+    match (threshold, std::fs::metadata(filename)) {
+        (Some(threshold), Ok(metadata)) => metadata.len() >= threshold,
+        _ => false,
+    }
+}
+
+/// Reads the files into a single timeline, each one with the rule for its filename,
+/// or with the rule given at the command line. Returns the files that are read whole
+/// even with a time range or tail, as they are compressed or not UTF-8.
+fn read_merged_files(
+    filenames: &[String],
+    explicit_rule: bool,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    tail: Option<Tail>,
+    app: &mut Application,
+) -> Vec<String> {
+    let files: Vec<(String, SourceRule)> = filenames
+        .iter()
+        .map(|filename| {
+            let rule = if explicit_rule {
+                app.state.current_rule.clone()
+            } else {
+                get_rule_by_filename(&mut app.state.settings, filename.clone())
+            };
+            let mut source_rule = SourceRule::default();
+            if let Err(err) = load_parsers(
+                &rule,
+                &app.state.settings.patterns,
+                &mut source_rule.parsers,
+            ) {
+                panic!("Could not load parsers for '{}': {:?}", filename, err);
+            }
+            match load_multiline(&rule) {
+                Ok(multiline) => source_rule.multiline = multiline,
+                Err(err) => panic!("Could not load multiline for '{}': {:?}", filename, err),
+            }
+            match load_encoding(&rule) {
+                Ok(encoding) => source_rule.encoding = encoding,
+                Err(err) => panic!("Could not load encoding for '{}': {:?}", filename, err),
+            }
+            (filename.clone(), source_rule)
+        })
+        .collect();
+
+    let read_whole = files
+        .iter()
+        .filter(|(filename, rule)| {
+            rule.encoding != Encoding::Utf8 || !matches!(Compression::detect(filename), Ok(None))
+        })
+        .map(|(filename, _)| filename.clone())
+        .collect();
+    app.state
+        .records
+        .readfiles_merged(files, since, until, tail, app.ui.tx.clone());
+    read_whole
+}
+
+/// Only uncompressed UTF-8 files can be read in part, the time range or tail of the
+/// `window` option is not applied to the rest.
+fn warn_read_whole(window: Option<&str>, filenames: &[String], app: &mut Application) {
+    if let Some(window) = window.filter(|_| !filenames.is_empty()) {
+        app.state.set_warning(format!(
+            "{} only apply to uncompressed UTF-8 files, read whole: {}",
+            window,
+            filenames.join(", ")
+        ));
+    }
+}
+
 fn execute_lua_script(script_path: &str, app: &mut Application) {
     // Ensure Lua console is initialized with welcome message
     app.state.ensure_lua_console_initialized();
@@ -252,10 +448,21 @@ fn set_rule_by_name(name: &str, app: &mut Application) {
     match rule {
         Some(rule) => {
             app.state.current_rule = rule;
-            if let Err(err) = load_parsers(&app.state.current_rule, &mut app.state.records.parsers)
-            {
+            if let Err(err) = load_parsers(
+                &app.state.current_rule,
+                &app.state.settings.patterns,
+                &mut app.state.records.parsers,
+            ) {
                 panic!("Could not load parsers for mode '{}': {:?}", name, err);
             }
+            match load_multiline(&app.state.current_rule) {
+                Ok(multiline) => app.state.records.multiline = multiline,
+                Err(err) => panic!("Could not load multiline for mode '{}': {:?}", name, err),
+            }
+            match load_encoding(&app.state.current_rule) {
+                Ok(encoding) => app.state.records.encoding = encoding,
+                Err(err) => panic!("Could not load encoding for mode '{}': {:?}", name, err),
+            }
         }
         None => {
             eprintln!("Error: Unknown mode '{}'", name);
@@ -288,9 +495,21 @@ fn set_rule_from_args(args: &Vec<String>, app: &mut Application) {
 
     app.state.current_rule = get_rule_by_filename(&mut app.state.settings, filename);
 
-    if let Err(err) = load_parsers(&app.state.current_rule, &mut app.state.records.parsers) {
+    if let Err(err) = load_parsers(
+        &app.state.current_rule,
+        &app.state.settings.patterns,
+        &mut app.state.records.parsers,
+    ) {
         panic!("Could not load parsers from settings: {:?}", err);
     }
+    match load_multiline(&app.state.current_rule) {
+        Ok(multiline) => app.state.records.multiline = multiline,
+        Err(err) => panic!("Could not load multiline from settings: {:?}", err),
+    }
+    match load_encoding(&app.state.current_rule) {
+        Ok(encoding) => app.state.records.encoding = encoding,
+        Err(err) => panic!("Could not load encoding from settings: {:?}", err),
+    }
 }
 
 // Checks if stdin is a file in contraswt to a tty
@@ -0,0 +1,221 @@
+//! Multiline record assembly
+//!
+//! Joins continuation lines, as stack traces, into the previous record so that
+//! filters, marks and the details view handle them as a single record.
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use regex::Regex;
+
+use crate::{events::TuiEvent, parser::ParserError, record::Record, settings::MultilineSettings};
+
+#[derive(Debug, Clone)]
+pub struct MultilineMatcher {
+    start: Option<Regex>,
+    continuation: Option<Regex>,
+    timeout: Duration,
+}
+
+impl MultilineMatcher {
+    pub fn new(settings: &MultilineSettings) -> Result<MultilineMatcher, ParserError> {
+        Ok(MultilineMatcher {
+            start: compile(settings.start.as_deref())?,
+            continuation: compile(settings.continuation.as_deref())?,
+            timeout: Duration::from_millis(settings.timeout_ms),
+        })
+    }
+
+    /// Whether this line belongs to the previous record instead of starting a new one.
+    pub fn is_continuation(&self, line: &str) -> bool {
+        if let Some(continuation) = &self.continuation {
+            if continuation.is_match(line) {
+                return true;
+            }
+        }
+        match &self.start {
+            Some(start) => !start.is_match(line),
+            None => false,
+        }
+    }
+
+    /// Groups the lines of a file into records. Returns the 1-based line number where
+    /// each record starts and its text, with the lines joined by `\n`.
+    pub fn group_lines(&self, lines: Vec<String>) -> Vec<(usize, String)> {
+        let mut groups: Vec<(usize, String)> = Vec::new();
+        for (i, line) in lines.into_iter().enumerate() {
+            if let Some((_, text)) = groups.last_mut() {
+                if self.is_continuation(&line) {
+                    text.push('\n');
+                    text.push_str(&line);
+                    continue;
+                }
+            }
+            groups.push((i + 1, line));
+        }
+        groups
+    }
+
+    /// Returns a sender that joins the continuation records sent to it and forwards the
+    /// complete records to `tx`.
+    ///
+    /// A record is kept pending until the next one starts, no new line arrives for the
+    /// configured timeout, or all the senders are dropped.
+    pub fn spawn_assembler(&self, tx: mpsc::Sender<TuiEvent>) -> mpsc::Sender<TuiEvent> {
+        let matcher = self.clone();
+        let (assembler_tx, rx) = mpsc::channel::<TuiEvent>();
+
+        // A plain thread, as it blocks for the whole life of the input and must not take a
+        // worker from the rayon pool used for parsing and filtering.
+        thread::spawn(move || {
+            let mut pending: Option<Record> = None;
+            loop {
+                let event = if pending.is_some() {
+                    rx.recv_timeout(matcher.timeout)
+                } else {
+                    rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected)
+                };
+
+                let ready = match event {
+                    Ok(TuiEvent::NewRecord(record)) => match pending.as_mut() {
+                        Some(previous) if matcher.is_continuation(&record.original) => {
+                            previous.append_line(&record);
+                            None
+                        }
+                        _ => pending.replace(record).map(TuiEvent::NewRecord),
+                    },
+                    Ok(event) => Some(event),
+                    Err(mpsc::RecvTimeoutError::Timeout) => pending.take().map(TuiEvent::NewRecord),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        if let Some(record) = pending.take() {
+                            let _ = tx.send(TuiEvent::NewRecord(record));
+                        }
+                        return;
+                    }
+                };
+
+                if let Some(event) = ready {
+                    if tx.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        assembler_tx
+    }
+}
+
+fn compile(pattern: Option<&str>) -> Result<Option<Regex>, ParserError> {
+    match pattern {
+        Some(pattern) => Regex::new(pattern)
+            .map(Some)
+            .map_err(|_| ParserError::InvalidParser(format!("multiline {}", pattern))),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(start: Option<&str>, continuation: Option<&str>) -> MultilineMatcher {
+        MultilineMatcher::new(&MultilineSettings {
+            start: start.map(|s| s.to_string()),
+            continuation: continuation.map(|s| s.to_string()),
+            timeout_ms: 50,
+        })
+        .unwrap()
+    }
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_group_lines_with_start() {
+        let matcher = matcher(Some(r"^\d{4}-\d{2}-\d{2}"), None);
+        let groups = matcher.group_lines(lines(&[
+            "2024-01-01 ERROR boom",
+            "Traceback (most recent call last):",
+            "  File \"app.py\", line 1",
+            "2024-01-01 INFO ok",
+        ]));
+        assert_eq!(
+            groups,
+            vec![
+                (
+                    1,
+                    "2024-01-01 ERROR boom\nTraceback (most recent call last):\n  File \"app.py\", line 1"
+                        .to_string()
+                ),
+                (4, "2024-01-01 INFO ok".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_lines_with_continuation() {
+        let matcher = matcher(None, Some(r"^(\s|Caused by:)"));
+        let groups = matcher.group_lines(lines(&[
+            "  orphan continuation",
+            "Exception in thread main",
+            "\tat Main.main(Main.java:3)",
+            "Caused by: java.io.IOException",
+            "next record",
+        ]));
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0], (1, "  orphan continuation".to_string()));
+        assert_eq!(groups[1].0, 2);
+        assert_eq!(groups[1].1.lines().count(), 3);
+        assert_eq!(groups[2], (5, "next record".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        let result = MultilineMatcher::new(&MultilineSettings {
+            start: Some("(".to_string()),
+            continuation: None,
+            timeout_ms: 50,
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_spawn_assembler_flushes_on_timeout_and_disconnect() {
+        let matcher = matcher(None, Some(r"^\s"));
+        let (tx, rx) = mpsc::channel();
+        let assembler = matcher.spawn_assembler(tx);
+
+        for line in ["first", "  more of first", "second"] {
+            assembler
+                .send(TuiEvent::NewRecord(Record::new(line.to_string())))
+                .unwrap();
+        }
+
+        let first = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        match first {
+            TuiEvent::NewRecord(record) => assert_eq!(record.original, "first\n  more of first"),
+            _ => panic!("Expected a record"),
+        }
+
+        // The last record is flushed after the timeout, without more input
+        let second = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        match second {
+            TuiEvent::NewRecord(record) => assert_eq!(record.original, "second"),
+            _ => panic!("Expected a record"),
+        }
+
+        assembler
+            .send(TuiEvent::NewRecord(Record::new("third".to_string())))
+            .unwrap();
+        drop(assembler);
+        let third = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        match third {
+            TuiEvent::NewRecord(record) => assert_eq!(record.original, "third"),
+            _ => panic!("Expected a record"),
+        }
+        assert!(rx.recv_timeout(Duration::from_secs(2)).is_err());
+    }
+}
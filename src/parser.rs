@@ -1,7 +1,10 @@
 use chrono::prelude::*;
-use chrono::NaiveDateTime;
+use regex::Regex;
 use std::{collections::HashMap, sync::RwLock};
 
+use crate::grok;
+use crate::value::{self, FieldType};
+
 #[derive(Debug)]
 pub struct CsvParser {
     pub headers: Vec<String>,
@@ -11,9 +14,13 @@ pub struct CsvParser {
 #[derive(Debug)]
 pub enum Parser {
     Regex(regex::Regex),
+    Grok(regex::Regex, Vec<(String, FieldType)>),
+    Types(Vec<(String, FieldType)>),
     LogFmt(regex::Regex),
     AutoDatetime,
     Csv(Box<RwLock<CsvParser>>),
+    Json,
+    Syslog,
     TransformTimestampIso8601,
 }
 
@@ -24,7 +31,17 @@ pub enum ParserError {
 }
 
 impl Parser {
+    /// Parser without user defined grok patterns, as the tests use.
+    #[cfg(test)]
     pub fn new(s: &str) -> Result<Parser, ParserError> {
+        Parser::new_with_patterns(s, &HashMap::new())
+    }
+
+    /// As `new`, with the user defined grok `patterns` from the settings.
+    pub fn new_with_patterns(
+        s: &str,
+        patterns: &HashMap<String, String>,
+    ) -> Result<Parser, ParserError> {
         // split frist word of and the rest as a string
         let mut parts = s.splitn(2, ' ');
         let first = parts.next().ok_or(ParserError::InvalidParser(s.into()))?;
@@ -38,12 +55,26 @@ impl Parser {
                 let rest = parts.next().ok_or(ParserError::InvalidParser(s.into()))?;
                 return Ok(Parser::new_from_pattern(rest));
             }
+            "grok" => {
+                let rest = parts.next().ok_or(ParserError::InvalidParser(s.into()))?;
+                return Parser::new_from_grok(rest, patterns);
+            }
+            "types" => {
+                let rest = parts.next().ok_or(ParserError::InvalidParser(s.into()))?;
+                return Ok(Parser::Types(value::parse_field_types(rest)?));
+            }
             "autodatetime" => {
                 return Ok(Parser::new_autodate());
             }
             "csv" => {
                 return Ok(Parser::new_csv());
             }
+            "json" => {
+                return Ok(Parser::new_json());
+            }
+            "syslog" => {
+                return Ok(Parser::new_syslog());
+            }
             "transform" => {
                 let rest = parts.next().ok_or(ParserError::InvalidParser(s.into()))?;
                 return Parser::new_transform(rest);
@@ -108,6 +139,14 @@ impl Parser {
         Parser::Regex(re)
     }
 
+    pub fn new_from_grok(
+        pattern: &str,
+        patterns: &HashMap<String, String>,
+    ) -> Result<Parser, ParserError> {
+        let (re, types) = grok::compile(pattern, patterns)?;
+        Ok(Parser::Grok(re, types))
+    }
+
     pub fn new_autodate() -> Parser {
         Parser::AutoDatetime
     }
@@ -119,6 +158,14 @@ impl Parser {
         })))
     }
 
+    pub fn new_json() -> Parser {
+        Parser::Json
+    }
+
+    pub fn new_syslog() -> Parser {
+        Parser::Syslog
+    }
+
     pub fn new_transform(transform_type: &str) -> Result<Parser, ParserError> {
         match transform_type {
             "timestamp iso8601" => Ok(Parser::TransformTimestampIso8601),
@@ -130,12 +177,23 @@ impl Parser {
         }
     }
 
+    /// Fields this parser declares a type for, as `%{NUMBER:bytes:int}` in grok.
+    pub fn field_types(&self) -> &[(String, FieldType)] {
+        match self {
+            Parser::Grok(_, types) | Parser::Types(types) => types,
+            _ => &[],
+        }
+    }
+
     pub fn parse_line(&self, data: HashMap<String, String>, line: &str) -> HashMap<String, String> {
         match self {
-            Parser::Regex(_) => self.parse_regex(data, line),
+            Parser::Regex(_) | Parser::Grok(_, _) => self.parse_regex(data, line),
+            Parser::Types(_) => data,
             Parser::AutoDatetime => self.parse_autodate(data, line),
             Parser::Csv(_) => self.parse_csv(data, line),
             Parser::LogFmt(_) => self.parse_logfmt(data, line),
+            Parser::Json => self.parse_json(data, line),
+            Parser::Syslog => self.parse_syslog(data, line),
             Parser::TransformTimestampIso8601 => self.parse_transform_timestamp_iso8601(data, line),
         }
     }
@@ -176,7 +234,7 @@ impl Parser {
         line: &str,
     ) -> HashMap<String, String> {
         let re: &regex::Regex = match self {
-            Parser::Regex(re) => re,
+            Parser::Regex(re) | Parser::Grok(re, _) => re,
             _ => panic!("Invalid parser type"),
         };
         let mut data = HashMap::new();
@@ -187,8 +245,10 @@ impl Parser {
                     if name.starts_with("_") {
                         continue; // ignore
                     }
-                    let value = caps[name].to_string();
-                    data.insert(name.to_string(), value);
+                    // Optional groups, as in grok patterns, may not match
+                    if let Some(value) = caps.name(name) {
+                        data.insert(name.to_string(), value.as_str().to_string());
+                    }
                 }
             }
             None => {}
@@ -264,6 +324,51 @@ impl Parser {
         parts
     }
 
+    fn parse_json(&self, mut data: HashMap<String, String>, line: &str) -> HashMap<String, String> {
+        // The JSON object may come after some prefix, as in `2024-01-01 INFO {...}`, so
+        // try from each `{` until one of them decodes as an object. Trailing text is ignored.
+        for (start, _) in line.match_indices('{') {
+            let mut stream =
+                serde_json::Deserializer::from_str(&line[start..]).into_iter::<serde_json::Value>();
+            if let Some(Ok(serde_json::Value::Object(object))) = stream.next() {
+                for (key, value) in object {
+                    flatten_json_value(key, value, &mut data);
+                }
+                return data;
+            }
+        }
+        data
+    }
+
+    fn parse_syslog(
+        &self,
+        mut data: HashMap<String, String>,
+        line: &str,
+    ) -> HashMap<String, String> {
+        if let Some(caps) = SYSLOG_RFC5424_RE.captures(line) {
+            insert_syslog_priority(&mut data, &caps["pri"]);
+            data.insert("version".to_string(), caps["version"].to_string());
+            for name in ["timestamp", "hostname", "appname", "procid", "msgid"] {
+                // "-" is the nil value
+                if &caps[name] != "-" {
+                    data.insert(name.to_string(), caps[name].to_string());
+                }
+            }
+            let message = parse_syslog_structured_data(&mut data, &caps["rest"]);
+            data.insert("message".to_string(), message.to_string());
+        } else if let Some(caps) = SYSLOG_RFC3164_RE.captures(line) {
+            if let Some(pri) = caps.name("pri") {
+                insert_syslog_priority(&mut data, pri.as_str());
+            }
+            for name in ["timestamp", "hostname", "appname", "procid", "message"] {
+                if let Some(value) = caps.name(name) {
+                    data.insert(name.to_string(), value.as_str().to_string());
+                }
+            }
+        }
+        data
+    }
+
     fn parse_transform_timestamp_iso8601(
         &self,
         mut data: HashMap<String, String>,
@@ -280,45 +385,153 @@ impl Parser {
     }
 
     fn convert_to_iso8601(&self, timestamp: &str) -> Option<String> {
-        // Try various timestamp formats and convert to ISO8601
+        // If we can't parse it, return None (keep original)
+        value::parse_timestamp_with_offset(timestamp).map(|dt| dt.to_rfc3339())
+    }
+}
 
-        // Format: "2024-01-01T12:30:45Z" (with Z) - already RFC3339
-        if let Ok(dt) = DateTime::parse_from_rfc3339(timestamp) {
-            return Some(dt.to_rfc3339());
-        }
+lazy_static::lazy_static! {
+    // <165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3"] message
+    static ref SYSLOG_RFC5424_RE: Regex = Regex::new(
+        r"^<(?P<pri>\d{1,3})>(?P<version>\d{1,2}) (?P<timestamp>\S+) (?P<hostname>\S+) (?P<appname>\S+) (?P<procid>\S+) (?P<msgid>\S+) ?(?P<rest>.*)$"
+    )
+    .unwrap();
+    // <34>Oct 11 22:14:15 mymachine su[123]: message, the priority is optional as in /var/log/syslog.
+    // Also accepts the ISO timestamps that current rsyslog writes.
+    static ref SYSLOG_RFC3164_RE: Regex = Regex::new(
+        r"^(?:<(?P<pri>\d{1,3})>)?(?P<timestamp>[A-Z][a-z]{2} [ \d]\d \d{2}:\d{2}:\d{2}|\d{4}-\d{2}-\d{2}T\S+) (?P<hostname>\S+) (?:(?P<appname>[^:\[\s]+)(?:\[(?P<procid>[^\]]*)\])?: ?)?(?P<message>.*)$"
+    )
+    .unwrap();
+}
 
-        // Format: "2024-01-01T12:30:45.123Z" (with milliseconds) - already RFC3339
-        if let Ok(dt) = DateTime::parse_from_rfc3339(timestamp) {
-            return Some(dt.to_rfc3339());
-        }
+const SYSLOG_FACILITIES: [&str; 24] = [
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+
+const SYSLOG_SEVERITIES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+/// The PRI is facility * 8 + severity. Both are stored by name.
+fn insert_syslog_priority(data: &mut HashMap<String, String>, pri: &str) {
+    let pri: usize = match pri.parse() {
+        Ok(pri) => pri,
+        Err(_) => return,
+    };
+    if let Some(facility) = SYSLOG_FACILITIES.get(pri / 8) {
+        data.insert("facility".to_string(), facility.to_string());
+    }
+    data.insert(
+        "severity".to_string(),
+        SYSLOG_SEVERITIES[pri % 8].to_string(),
+    );
+}
 
-        // Format: "02/Jan/2024:12:30:45 +0100" (nginx/apache format)
-        if let Ok(dt) = DateTime::parse_from_str(timestamp, "%d/%b/%Y:%H:%M:%S %z") {
-            return Some(dt.to_rfc3339());
-        }
+/// Parses the RFC 5424 structured data at the start of `rest`, as
+/// `[origin@123 ip="1.2.3.4"][meta seq="1"]` or `-`, and returns the message after it.
+///
+/// Each param is stored as `<sd-id>.<name>`, without the `@enterprise` suffix so it
+/// can be used in filters: `origin.ip`, `meta.seq`.
+fn parse_syslog_structured_data<'a>(data: &mut HashMap<String, String>, rest: &'a str) -> &'a str {
+    if let Some(message) = rest.strip_prefix('-') {
+        return message.strip_prefix(' ').unwrap_or(message);
+    }
 
-        // Format: "2024-01-01 12:30:45" (space separated) - assume UTC
-        if let Ok(naive_dt) = NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S") {
-            let utc_dt = DateTime::<Utc>::from_naive_utc_and_offset(naive_dt, Utc);
-            return Some(utc_dt.to_rfc3339());
+    let mut rest = rest;
+    while let Some(element) = rest.strip_prefix('[') {
+        let id_end = match element.find([' ', ']']) {
+            Some(id_end) => id_end,
+            None => return rest,
+        };
+        let id = element[..id_end].split('@').next().unwrap_or_default();
+        let mut chars = element[id_end..].char_indices().peekable();
+        let mut end = None;
+        let mut name = String::new();
+        let mut value = String::new();
+        let mut in_value = false;
+        while let Some((i, c)) = chars.next() {
+            if in_value {
+                match c {
+                    '\\' => {
+                        if let Some((_, escaped)) = chars.next() {
+                            value.push(escaped);
+                        }
+                    }
+                    '"' => {
+                        data.insert(format!("{}.{}", id, name), value.clone());
+                        name.clear();
+                        value.clear();
+                        in_value = false;
+                    }
+                    _ => value.push(c),
+                }
+                continue;
+            }
+            match c {
+                ']' => {
+                    end = Some(id_end + i + 1);
+                    break;
+                }
+                ' ' | '=' => {}
+                '"' => in_value = true,
+                _ => name.push(c),
+            }
         }
-
-        // Format: "2024-01-01T12:30:45" (T separated) - assume UTC
-        if let Ok(naive_dt) = NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S") {
-            let utc_dt = DateTime::<Utc>::from_naive_utc_and_offset(naive_dt, Utc);
-            return Some(utc_dt.to_rfc3339());
+        match end {
+            Some(end) => rest = &element[end..],
+            None => return rest,
         }
+    }
+    rest.strip_prefix(' ').unwrap_or(rest)
+}
 
-        // Format: "Jan 02 12:30:45" (syslog format) - assume UTC and current year
-        if let Ok(naive_dt) = NaiveDateTime::parse_from_str(timestamp, "%b %d %H:%M:%S") {
-            let now = Utc::now();
-            let dt_with_year = naive_dt.with_year(now.year()).unwrap_or(naive_dt);
-            let utc_dt = DateTime::<Utc>::from_naive_utc_and_offset(dt_with_year, Utc);
-            return Some(utc_dt.to_rfc3339());
+/// Inserts the value at `key`, with nested objects and arrays flattened into dotted
+/// paths, so `{"http":{"status":500},"tags":["a"]}` gives `http.status` and `tags.0`.
+fn flatten_json_value(key: String, value: serde_json::Value, data: &mut HashMap<String, String>) {
+    match value {
+        serde_json::Value::Object(object) if !object.is_empty() => {
+            for (subkey, subvalue) in object {
+                flatten_json_value(format!("{}.{}", key, subkey), subvalue, data);
+            }
+        }
+        serde_json::Value::Array(array) if !array.is_empty() => {
+            for (i, subvalue) in array.into_iter().enumerate() {
+                flatten_json_value(format!("{}.{}", key, i), subvalue, data);
+            }
+        }
+        serde_json::Value::String(s) => {
+            data.insert(key, s);
+        }
+        serde_json::Value::Null => {
+            data.insert(key, "".to_string());
+        }
+        // Numbers, booleans and empty objects or arrays keep their JSON representation
+        other => {
+            data.insert(key, other.to_string());
         }
-
-        // If we can't parse it, return None (keep original)
-        None
     }
 }
 
@@ -376,6 +589,10 @@ mod tests {
         // Test csv
         let parser = Parser::new("csv").unwrap();
         matches!(parser, Parser::Csv(_));
+
+        // Test json
+        let parser = Parser::new("json").unwrap();
+        matches!(parser, Parser::Json);
     }
 
     #[test]
@@ -533,6 +750,199 @@ mod tests {
         assert_eq!(result.get("city"), Some(&"LA".to_string()));
     }
 
+    #[test]
+    fn test_parse_json() {
+        let parser = Parser::new_json();
+
+        // Whole line is a JSON object
+        let result = parser.parse_line(
+            HashMap::new(),
+            r#"{"level":"error","msg":"boom","status":500,"ok":false,"user":null}"#,
+        );
+        assert_eq!(result.get("level"), Some(&"error".to_string()));
+        assert_eq!(result.get("msg"), Some(&"boom".to_string()));
+        assert_eq!(result.get("status"), Some(&"500".to_string()));
+        assert_eq!(result.get("ok"), Some(&"false".to_string()));
+        assert_eq!(result.get("user"), Some(&"".to_string()));
+
+        // JSON object after a non JSON prefix, and trailing text
+        let result = parser.parse_line(
+            HashMap::new(),
+            r#"2024-01-01 INFO {"msg":"hello {world}","count":3} trailing"#,
+        );
+        assert_eq!(result.get("msg"), Some(&"hello {world}".to_string()));
+        assert_eq!(result.get("count"), Some(&"3".to_string()));
+
+        // A `{` in the prefix that is not JSON is skipped
+        let result = parser.parse_line(HashMap::new(), r#"worker{3} {"msg":"ok"}"#);
+        assert_eq!(result.get("msg"), Some(&"ok".to_string()));
+
+        // Not JSON keeps the previous data untouched
+        let mut data = HashMap::new();
+        data.insert("word_count".to_string(), "2".to_string());
+        let result = parser.parse_line(data, "plain text");
+        assert_eq!(result.len(), 1);
+        assert_eq!(result.get("word_count"), Some(&"2".to_string()));
+    }
+
+    #[test]
+    fn test_parse_json_nested() {
+        let parser = Parser::new_json();
+
+        let result = parser.parse_line(
+            HashMap::new(),
+            r#"{"http":{"status":500,"headers":{"user_agent":"curl"}},"tags":["a",{"b":1}],"empty":{},"none":[]}"#,
+        );
+        assert_eq!(result.get("http.status"), Some(&"500".to_string()));
+        assert_eq!(
+            result.get("http.headers.user_agent"),
+            Some(&"curl".to_string())
+        );
+        assert_eq!(result.get("tags.0"), Some(&"a".to_string()));
+        assert_eq!(result.get("tags.1.b"), Some(&"1".to_string()));
+        assert_eq!(result.get("empty"), Some(&"{}".to_string()));
+        assert_eq!(result.get("none"), Some(&"[]".to_string()));
+        // Only the leaves are stored
+        assert!(!result.contains_key("http"));
+        assert!(!result.contains_key("tags"));
+    }
+
+    #[test]
+    fn test_parse_syslog_rfc5424() {
+        let parser = Parser::new("syslog").unwrap();
+
+        let result = parser.parse_line(
+            HashMap::new(),
+            r#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="Application" eventID="1011"][meta quote="a \"b\" \]c"] An application event"#,
+        );
+        assert_eq!(result.get("facility"), Some(&"local4".to_string()));
+        assert_eq!(result.get("severity"), Some(&"notice".to_string()));
+        assert_eq!(
+            result.get("timestamp"),
+            Some(&"2003-10-11T22:14:15.003Z".to_string())
+        );
+        assert_eq!(
+            result.get("hostname"),
+            Some(&"mymachine.example.com".to_string())
+        );
+        assert_eq!(result.get("appname"), Some(&"evntslog".to_string()));
+        assert_eq!(result.get("procid"), None);
+        assert_eq!(result.get("msgid"), Some(&"ID47".to_string()));
+        assert_eq!(result.get("exampleSDID.iut"), Some(&"3".to_string()));
+        assert_eq!(
+            result.get("exampleSDID.eventSource"),
+            Some(&"Application".to_string())
+        );
+        assert_eq!(result.get("meta.quote"), Some(&"a \"b\" ]c".to_string()));
+        assert_eq!(
+            result.get("message"),
+            Some(&"An application event".to_string())
+        );
+
+        // Nil structured data
+        let result = parser.parse_line(
+            HashMap::new(),
+            "<34>1 2003-10-11T22:14:15.003Z host su 123 ID47 - 'su root' failed",
+        );
+        assert_eq!(result.get("facility"), Some(&"auth".to_string()));
+        assert_eq!(result.get("severity"), Some(&"crit".to_string()));
+        assert_eq!(result.get("procid"), Some(&"123".to_string()));
+        assert_eq!(result.get("message"), Some(&"'su root' failed".to_string()));
+    }
+
+    #[test]
+    fn test_parse_syslog_rfc3164() {
+        let parser = Parser::new("syslog").unwrap();
+
+        let result = parser.parse_line(
+            HashMap::new(),
+            "<34>Oct 11 22:14:15 mymachine su[230]: 'su root' failed for lonvick on /dev/pts/8",
+        );
+        assert_eq!(result.get("facility"), Some(&"auth".to_string()));
+        assert_eq!(result.get("severity"), Some(&"crit".to_string()));
+        assert_eq!(
+            result.get("timestamp"),
+            Some(&"Oct 11 22:14:15".to_string())
+        );
+        assert_eq!(result.get("hostname"), Some(&"mymachine".to_string()));
+        assert_eq!(result.get("appname"), Some(&"su".to_string()));
+        assert_eq!(result.get("procid"), Some(&"230".to_string()));
+        assert_eq!(
+            result.get("message"),
+            Some(&"'su root' failed for lonvick on /dev/pts/8".to_string())
+        );
+
+        // /var/log/syslog format, without priority
+        let result = parser.parse_line(
+            HashMap::new(),
+            "Jan  2 03:04:05 laptop kernel: usb 1-1: new device",
+        );
+        assert_eq!(result.get("facility"), None);
+        assert_eq!(
+            result.get("timestamp"),
+            Some(&"Jan  2 03:04:05".to_string())
+        );
+        assert_eq!(result.get("hostname"), Some(&"laptop".to_string()));
+        assert_eq!(result.get("appname"), Some(&"kernel".to_string()));
+        assert_eq!(result.get("procid"), None);
+        assert_eq!(
+            result.get("message"),
+            Some(&"usb 1-1: new device".to_string())
+        );
+
+        // rsyslog with ISO timestamps
+        let result = parser.parse_line(
+            HashMap::new(),
+            "2024-03-01T10:00:00.123456+01:00 server systemd[1]: Started cron.",
+        );
+        assert_eq!(
+            result.get("timestamp"),
+            Some(&"2024-03-01T10:00:00.123456+01:00".to_string())
+        );
+        assert_eq!(result.get("appname"), Some(&"systemd".to_string()));
+        assert_eq!(result.get("procid"), Some(&"1".to_string()));
+
+        // Not syslog, keeps data
+        let result = parser.parse_line(HashMap::new(), "not a syslog line");
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_parse_grok() {
+        let parser = Parser::new(
+            r#"grok %{IPORHOST:ip} - %{USER:user} \[%{HTTPDATE:timestamp}\] "%{WORD:method} %{URIPATHPARAM:url} HTTP/%{NUMBER:version}" %{INT:status} (?:%{INT:bytes}|-)"#,
+        )
+        .unwrap();
+        let result = parser.parse_line(
+            HashMap::new(),
+            r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif?x=1 HTTP/1.0" 200 -"#,
+        );
+        assert_eq!(result.get("ip"), Some(&"127.0.0.1".to_string()));
+        assert_eq!(result.get("user"), Some(&"frank".to_string()));
+        assert_eq!(
+            result.get("timestamp"),
+            Some(&"10/Oct/2000:13:55:36 -0700".to_string())
+        );
+        assert_eq!(result.get("method"), Some(&"GET".to_string()));
+        assert_eq!(result.get("url"), Some(&"/apache_pb.gif?x=1".to_string()));
+        assert_eq!(result.get("status"), Some(&"200".to_string()));
+        // Optional group did not match
+        assert_eq!(result.get("bytes"), None);
+
+        let mut patterns = HashMap::new();
+        patterns.insert("ACTION".to_string(), "install|remove".to_string());
+        let parser =
+            Parser::new_with_patterns("grok %{ACTION:action} %{NOTSPACE:package}", &patterns)
+                .unwrap();
+        let result = parser.parse_line(HashMap::new(), "install vim:amd64");
+        assert_eq!(result.get("action"), Some(&"install".to_string()));
+        assert_eq!(result.get("package"), Some(&"vim:amd64".to_string()));
+
+        assert!(Parser::new("grok %{UNKNOWN:x}").is_err());
+        assert!(Parser::new("grok %{INT:x:complex}").is_err());
+        assert!(Parser::new("grok").is_err());
+    }
+
     #[test]
     fn test_csv_read_data() {
         let parser = Parser::new_csv();
@@ -622,7 +1032,6 @@ mod tests {
         let unknown_types = vec![
             "transform",
             "transform unknown_format",
-            "json",
             "xml",
             "yaml",
             "unknown_parser_type",
@@ -1,13 +1,23 @@
 use regex::Regex;
 use std::collections::HashMap;
 
-use crate::{ast::AST, parser::Parser};
+use crate::{parser::Parser, value::Value};
 
 #[derive(Debug, Default, Clone)]
 pub struct Record {
     pub original: String,
     pub data: HashMap<String, String>,
+    /// Typed values of the fields that the extractors declared a type for
+    pub values: HashMap<String, Value>,
+    /// Position in the visible records
     pub index: usize,
+    /// Line number in the source, if known. Also as the `line_number` field.
+    pub line_number: Option<usize>,
+    /// Offset of the line in the source, if known. Also as the `byte_offset` field.
+    pub byte_offset: Option<u64>,
+    /// The line as read, only if it was not valid UTF-8 and `original` has replacement
+    /// characters instead of the invalid bytes
+    pub bytes: Option<Vec<u8>>,
 }
 
 impl Record {
@@ -15,30 +25,91 @@ impl Record {
         Record {
             original: line,
             data: HashMap::new(),
+            values: HashMap::new(),
             index: 0,
+            line_number: None,
+            byte_offset: None,
+            bytes: None,
         }
     }
 
+    /// A record for a line read as bytes. Invalid UTF-8 is replaced, and the bytes kept.
+    pub fn from_bytes(line: &[u8]) -> Record {
+        match String::from_utf8_lossy(line) {
+            std::borrow::Cow::Borrowed(text) => Record::new(text.to_string()),
+            std::borrow::Cow::Owned(text) => {
+                let mut record = Record::new(text);
+                record.bytes = Some(line.to_vec());
+                record
+            }
+        }
+    }
+
+    /// The line as read, with the invalid bytes if any.
+    pub fn original_bytes(&self) -> &[u8] {
+        self.bytes.as_deref().unwrap_or(self.original.as_bytes())
+    }
+
+    /// Joins the line of a multiline record that continues this one.
+    pub fn append_line(&mut self, continuation: &Record) {
+        if self.bytes.is_some() || continuation.bytes.is_some() {
+            let mut bytes = self.original_bytes().to_vec();
+            bytes.push(b'\n');
+            bytes.extend_from_slice(continuation.original_bytes());
+            self.bytes = Some(bytes);
+        }
+        self.original.push('\n');
+        self.original.push_str(&continuation.original);
+    }
+
     // pub fn has_data(&self, key: &str) -> bool {
     //     self.data.contains_key(key)
     // }
     pub fn set_data(&mut self, key: &str, value: String) {
+        self.values.remove(key);
         self.data.insert(key.to_string(), value);
     }
     pub fn get(&self, key: &str) -> Option<&String> {
         self.data.get(key)
     }
+    pub fn get_value(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
     pub fn unset_data(&mut self, key: &str) {
+        self.values.remove(key);
         self.data.remove(key);
     }
 
-    pub fn set_line_number(&mut self, line_number: usize) {
-        self.index = line_number;
+    /// Where the line is in its source file or stream.
+    pub fn set_source_position(&mut self, line_number: Option<usize>, byte_offset: u64) {
+        if let Some(line_number) = line_number {
+            self.set_data("line_number", line_number.to_string());
+        }
+        self.set_data("byte_offset", byte_offset.to_string());
+        self.line_number = line_number;
+        self.byte_offset = Some(byte_offset);
     }
 
     pub fn parse(&mut self, parsers: &Vec<Parser>) {
         let data = Record::parse_line(&self.original, parsers);
         self.data.extend(data);
+
+        for parser in parsers {
+            for (field, field_type) in parser.field_types() {
+                match self
+                    .data
+                    .get(field)
+                    .and_then(|value| field_type.parse(value))
+                {
+                    Some(value) => {
+                        self.values.insert(field.clone(), value);
+                    }
+                    None => {
+                        self.values.remove(field);
+                    }
+                }
+            }
+        }
     }
 
     pub fn parse_line(line: &str, parsers: &Vec<Parser>) -> HashMap<String, String> {
@@ -55,10 +126,6 @@ impl Record {
 
         data
     }
-
-    pub fn matches(&self, search: &AST) -> bool {
-        search.matches(self)
-    }
 }
 
 lazy_static::lazy_static! {
@@ -2,27 +2,261 @@ use nix::sys::signal::{kill, Signal};
 use nix::unistd::Pid;
 use notify::Watcher;
 use rayon::{prelude::*, spawn};
-use std::thread::sleep;
-use std::time::Duration;
+use std::net::{TcpListener, UdpSocket};
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::os::unix::net::{UnixDatagram, UnixListener};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
 use std::{
+    collections::HashMap,
     io::{BufRead, Read, Seek},
-    path::Path,
+    ops::Range,
+    path::{Path, PathBuf},
     process::Stdio,
     sync::mpsc,
 };
 
+use chrono::{DateTime, Utc};
+
+use crate::decompress::Compression;
+use crate::encoding::Encoding;
+use crate::lazyfile::LazyFile;
+use crate::multiline::MultilineMatcher;
 use crate::parser;
-use crate::settings::RulesSettings;
-use crate::{ast::AST, events::TuiEvent, parser::Parser, record::Record};
+use crate::settings::{GlobalSettings, RulesSettings};
+use crate::value::{self, Value};
+use crate::{
+    ast::AST,
+    events::{LoadProgress, TuiEvent},
+    filter::Filter,
+    parser::Parser,
+    record::Record,
+};
+
+/// Records sent at once to the application while loading a file
+const LOAD_BATCH_SIZE: usize = 10_000;
+/// Time after which the records read are sent, even if less than a batch
+const LOAD_BATCH_INTERVAL: Duration = Duration::from_millis(100);
+/// Bytes read at once when looking for line starts backwards
+const TAIL_CHUNK_SIZE: u64 = 64 * 1024;
+/// Lines looked at for a timestamp at each step of the search by time
+const TIME_SEARCH_LINES: usize = 1000;
 
 #[derive(Debug, Default)]
 pub struct RecordList {
     pub all_records: Vec<Record>,
     pub visible_records: Vec<Record>,
     pub parsers: Vec<Parser>,
-    pub filter: Option<AST>,
+    pub filter: Option<Filter>,
     pub child_process: Option<u32>,
     pub max_record_size: usize,
+    pub multiline: Option<MultilineMatcher>,
+    /// Names of the merged inputs, in order. Records from them have a `source` field.
+    pub sources: Vec<String>,
+    /// Rule of each merged input, by source name
+    pub source_rules: HashMap<String, SourceRule>,
+    pub follow: FollowOptions,
+    /// Memory mapped file, for files too big to be read. Then the records are only
+    /// parsed when needed, and `all_records` and `visible_records` are not used.
+    pub lazy: Option<LazyFile>,
+    /// Maximum number of records kept. The oldest are dropped.
+    pub max_records: Option<usize>,
+    /// Marked records are never dropped
+    pub pin_marks: bool,
+    /// Number of records dropped, to keep below `max_records`
+    pub dropped: usize,
+    /// Number of visible records dropped, to keep the position on the same record
+    pub dropped_visible: usize,
+    /// Encoding of the inputs of the rule, transcoded to UTF-8 as they are read
+    pub encoding: Encoding,
+    /// The start of the file not read yet, when only its tail was loaded. A single
+    /// file, so the start of merged files read with a tail can not be loaded later.
+    pub older: Option<OlderContent>,
+}
+
+/// Part of a file loaded, from its end
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tail {
+    Lines(usize),
+    Bytes(u64),
+}
+
+/// The file whose tail was loaded, and where the loaded part starts
+#[derive(Debug)]
+pub struct OlderContent {
+    pub filename: String,
+    pub start: u64,
+}
+
+/// Where a line starts in its source. The line number is not known when the start of
+/// the file was not read, as with `--lines` or `--since`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilePosition {
+    pub offset: u64,
+    pub line_number: Option<usize>,
+}
+
+impl FilePosition {
+    pub const START: FilePosition = FilePosition {
+        offset: 0,
+        line_number: Some(1),
+    };
+
+    /// At `offset`, with the line number only known if it is the start of the file.
+    pub fn at(offset: u64) -> FilePosition {
+        match offset {
+            0 => FilePosition::START,
+            _ => FilePosition {
+                offset,
+                line_number: None,
+            },
+        }
+    }
+
+    /// Not before `text_start`, where the text of the file starts after a BOM.
+    fn in_text(self, text_start: u64) -> FilePosition {
+        FilePosition {
+            offset: self.offset.max(text_start),
+            ..self
+        }
+    }
+
+    /// The position after a line of `size` bytes.
+    fn advance(&mut self, size: u64) {
+        self.offset += size;
+        self.line_number = self.line_number.map(|line_number| line_number + 1);
+    }
+}
+
+/// What to do when a followed file is rotated or truncated, and how to read it
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FollowOptions {
+    pub reload_on_truncate: bool,
+    pub separator: bool,
+    pub encoding: Encoding,
+}
+
+impl FollowOptions {
+    pub fn new(settings: &GlobalSettings) -> FollowOptions {
+        FollowOptions {
+            reload_on_truncate: settings.reload_on_truncate,
+            separator: settings.rotation_separator,
+            encoding: Encoding::Utf8,
+        }
+    }
+}
+
+/// A file read from a background thread
+struct Load {
+    filename: String,
+    /// Set on the records of merged inputs
+    source: Option<String>,
+    encoding: Encoding,
+    multiline: Option<MultilineMatcher>,
+    /// Where the reader starts
+    position: FilePosition,
+    total: u64,
+    /// Bytes read, for the progress
+    read: Arc<AtomicU64>,
+    /// Lines already read
+    line_count: usize,
+    /// Followed after it is read, if set
+    follow: Option<FollowOptions>,
+}
+
+impl Load {
+    fn spawn(self, mut reader: impl BufRead + Send + 'static, tx: mpsc::Sender<TuiEvent>) {
+        let Load {
+            filename,
+            source,
+            encoding,
+            multiline,
+            mut position,
+            total,
+            read,
+            mut line_count,
+            follow,
+        } = self;
+        // A plain thread, as it blocks until the whole file is read, and then follows it.
+        thread::spawn(move || {
+            let started = Instant::now();
+            let mut last_sent = started;
+            let mut batch: Vec<Record> = Vec::new();
+            let mut pending: Option<Record> = None;
+            let mut buffer = Vec::new();
+            loop {
+                buffer.clear();
+                let done = matches!(reader.read_until(b'\n', &mut buffer), Ok(0) | Err(_));
+                if !done {
+                    line_count += 1;
+                    let mut record = line_record(&buffer, &filename, position);
+                    position.advance(line_len(&buffer, encoding));
+                    if let Some(source) = &source {
+                        record.set_data("source", source.clone());
+                    }
+                    match (&multiline, pending.as_mut()) {
+                        (Some(multiline), Some(previous))
+                            if multiline.is_continuation(&record.original) =>
+                        {
+                            previous.append_line(&record);
+                        }
+                        (Some(_), _) => batch.extend(pending.replace(record)),
+                        (None, _) => batch.push(record),
+                    }
+                } else {
+                    batch.extend(pending.take());
+                }
+
+                if done
+                    || batch.len() >= LOAD_BATCH_SIZE
+                    || last_sent.elapsed() >= LOAD_BATCH_INTERVAL
+                {
+                    if !batch.is_empty()
+                        && tx
+                            .send(TuiEvent::NewRecords(std::mem::take(&mut batch)))
+                            .is_err()
+                    {
+                        return;
+                    }
+                    let progress = LoadProgress {
+                        filename: filename.clone(),
+                        read: read.load(Ordering::Relaxed),
+                        total,
+                        lines: line_count,
+                        elapsed: started.elapsed(),
+                        done,
+                    };
+                    if tx.send(TuiEvent::LoadProgress(progress)).is_err() {
+                        return;
+                    }
+                    last_sent = Instant::now();
+                }
+                if done {
+                    break;
+                }
+            }
+
+            if let Some(follow) = follow {
+                RecordList::wait_for_changes(
+                    filename,
+                    source,
+                    sender_for(multiline.as_ref(), tx),
+                    position,
+                    follow,
+                );
+            }
+        });
+    }
+}
+
+/// Extractors of an input that has its own rule, when several files are merged.
+#[derive(Debug, Default)]
+pub struct SourceRule {
+    pub parsers: Vec<Parser>,
+    pub multiline: Option<MultilineMatcher>,
+    pub encoding: Encoding,
 }
 
 impl RecordList {
@@ -34,6 +268,17 @@ impl RecordList {
             filter: None,
             child_process: None,
             max_record_size: 0,
+            multiline: None,
+            sources: Vec::new(),
+            source_rules: HashMap::new(),
+            follow: FollowOptions::default(),
+            lazy: None,
+            max_records: None,
+            pin_marks: true,
+            dropped: 0,
+            dropped_visible: 0,
+            encoding: Encoding::Utf8,
+            older: None,
         }
     }
 
@@ -48,147 +293,542 @@ impl RecordList {
     //     }
     // }
 
-    pub fn readfile_gz(&mut self, filename: &str) {
+    /// Reads a compressed file in the background, sending the records as they are
+    /// decoded. It is not followed, as compressed files are usually rotated logs.
+    pub fn readfile_compressed(
+        &mut self,
+        filename: &str,
+        compression: Compression,
+        tx: mpsc::Sender<TuiEvent>,
+    ) {
         let file = match std::fs::File::open(filename) {
             Ok(file) => file,
             Err(_error) => panic!("Could not open file={:?}", filename),
         };
+        let total = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        let read = Arc::new(AtomicU64::new(0));
+        let decoder = match compression.decoder(CountingReader::new(file, read.clone())) {
+            Ok(decoder) => decoder,
+            Err(error) => panic!("Could not decompress file={:?}: {}", filename, error),
+        };
+        // Offsets are in the decompressed content
+        self.load_in_background(
+            std::io::BufReader::new(self.encoding.reader(decoder)),
+            filename,
+            FilePosition::START,
+            total,
+            read,
+            false,
+            tx,
+        );
+    }
 
-        let reader = std::io::BufReader::new(file);
-        let mut decoder = flate2::read::GzDecoder::new(reader);
-        let mut buffer = String::new();
-        decoder.read_to_string(&mut buffer).unwrap();
+    /// Reads the file in the background, and then follows it. With `tail`, only the end
+    /// of the file is read, and the older lines with `load_older`. Line starts are found
+    /// looking for line ends backwards, so only on UTF-8 files.
+    pub fn readfile_parallel(
+        &mut self,
+        filename: &str,
+        tail: Option<Tail>,
+        tx: mpsc::Sender<TuiEvent>,
+    ) {
+        let mut file = match std::fs::File::open(filename) {
+            Ok(file) => file,
+            Err(_error) => panic!("Could not open file={:?}", filename),
+        };
+        let total = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        let start = match tail.filter(|_| self.encoding == Encoding::Utf8) {
+            Some(tail) => tail_start(&mut file, total, tail).unwrap_or(0),
+            None => 0,
+        };
+        self.readfile_window(filename, file, start, None, tx);
+    }
 
-        let lines: Vec<String> = buffer.lines().map(|line| line.to_string()).collect();
-        let records: Vec<Record> = lines
-            .par_iter()
-            .enumerate()
-            .map(|(line_number, line)| {
-                let mut record = Record::new(line.clone());
-                record.set_data("filename", filename.to_string());
-                record.set_data("line_number", (line_number + 1).to_string());
-                record.parse(&self.parsers);
-                record
-            })
-            .collect();
+    /// Reads only the records between the times, as `--since` and `--until`. The file
+    /// is sorted by time, so where they are is found with a binary search by the
+    /// `timestamp` of the lines. Without `until`, it is then followed.
+    pub fn readfile_time_range(
+        &mut self,
+        filename: &str,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tx: mpsc::Sender<TuiEvent>,
+    ) {
+        let file = match std::fs::File::open(filename) {
+            Ok(file) => file,
+            Err(_error) => panic!("Could not open file={:?}", filename),
+        };
+        let total = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        if self.encoding != Encoding::Utf8 {
+            return self.readfile_window(filename, file, 0, None, tx);
+        }
+        match time_window(&file, total, &self.parsers, since, until) {
+            Ok((start, end)) => self.readfile_window(filename, file, start, end, tx),
+            Err(error) => panic!("Could not read file={:?}: {}", filename, error),
+        }
+    }
 
-        self.visible_records = records.clone();
-        self.all_records.extend(records);
-        self.renumber();
-        self.max_record_size = self
-            .visible_records
-            .iter()
-            .map(|r| r.original.len())
-            .max()
-            .unwrap_or(0);
+    /// Reads the part of the file from `start` to `end`, or to the end and then follows
+    /// it. The lines before `start` can be loaded later with `load_older`.
+    fn readfile_window(
+        &mut self,
+        filename: &str,
+        mut file: std::fs::File,
+        start: u64,
+        end: Option<u64>,
+        tx: mpsc::Sender<TuiEvent>,
+    ) {
+        // Kept, as the byte order of the BOM is needed to follow it and load older lines
+        let (encoding, text_start) = file_encoding(&file, self.encoding);
+        self.encoding = encoding;
+        let position = FilePosition::at(start).in_text(text_start);
+        let start = position.offset;
+        if let Err(error) = file.seek(std::io::SeekFrom::Start(start)) {
+            panic!("Could not read file={:?}: {}", filename, error);
+        }
+        if start > text_start {
+            self.older = Some(OlderContent {
+                filename: filename.to_string(),
+                start,
+            });
+        }
+        let total = match end {
+            Some(end) => end,
+            None => file.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+        };
+        // Counts from the start, as following continues from there
+        let read = Arc::new(AtomicU64::new(start));
+        let reader = CountingReader::new(file, read.clone());
+        let reader: Box<dyn Read + Send> = match end {
+            Some(end) => Box::new(reader.take(end.saturating_sub(start))),
+            None => Box::new(reader),
+        };
+        let reader = std::io::BufReader::new(self.encoding.reader(reader));
+        self.load_in_background(reader, filename, position, total, read, end.is_none(), tx);
     }
 
-    pub fn readfile_parallel(&mut self, filename: &str, tx: mpsc::Sender<TuiEvent>) {
-        let file = match std::fs::File::open(filename) {
+    /// Sends the lines of the reader from a thread, in batches of records, with the
+    /// progress after each batch. Multiline records are joined there.
+    ///
+    /// The first line is read now, as some parsers (csv) learn from it before the rule
+    /// columns are set up. Not with multiline rules, as it may continue.
+    #[allow(clippy::too_many_arguments)]
+    fn load_in_background(
+        &mut self,
+        mut reader: impl BufRead + Send + 'static,
+        filename: &str,
+        mut position: FilePosition,
+        total: u64,
+        read: Arc<AtomicU64>,
+        follow: bool,
+        tx: mpsc::Sender<TuiEvent>,
+    ) {
+        let encoding = self.encoding;
+        let mut buffer = Vec::new();
+        let mut line_count = 0;
+        if self.multiline.is_none() && matches!(reader.read_until(b'\n', &mut buffer), Ok(1..)) {
+            line_count += 1;
+            self.add_records(vec![line_record(&buffer, filename, position)]);
+            position.advance(line_len(&buffer, encoding));
+        }
+
+        let load = Load {
+            filename: filename.to_string(),
+            source: None,
+            encoding,
+            multiline: self.multiline.clone(),
+            position,
+            total,
+            read,
+            line_count,
+            follow: follow.then(|| self.follow_options()),
+        };
+        load.spawn(reader, tx);
+    }
+
+    /// Loads up to `lines` lines before the loaded tail of the file, and returns how many
+    /// visible records were added at the start.
+    pub fn load_older(&mut self, lines: usize) -> usize {
+        let older = match &self.older {
+            Some(older) => older,
+            None => return 0,
+        };
+        let mut file = match std::fs::File::open(&older.filename) {
             Ok(file) => file,
+            Err(_) => return 0,
+        };
+        let start = match lines_start(&mut file, older.start, lines) {
+            Ok(start) => start,
+            Err(_) => return 0,
+        };
+        if file.seek(std::io::SeekFrom::Start(start)).is_err() {
+            return 0;
+        }
+        let lines = read_lines(
+            file.take(older.start - start),
+            FilePosition::at(start),
+            Encoding::Utf8,
+        );
+        let records = Self::records_from_lines(
+            &self.parsers,
+            self.multiline.as_ref(),
+            &older.filename,
+            lines,
+        );
+
+        let visible: Vec<Record> = match &self.filter {
+            Some(filter) => records
+                .iter()
+                .filter(|record| filter.matches(record))
+                .cloned()
+                .collect(),
+            None => records.clone(),
+        };
+        let added = visible.len();
+        let max_record_size = records.iter().map(|r| r.original.len()).max();
+        self.max_record_size = self.max_record_size.max(max_record_size.unwrap_or(0));
+        self.all_records.splice(0..0, records);
+        self.visible_records.splice(0..0, visible);
+        self.renumber();
+
+        self.older = match start {
+            0 => None,
+            _ => self
+                .older
+                .take()
+                .map(|older| OlderContent { start, ..older }),
+        };
+        added
+    }
+
+    /// Maps the file and only indexes the lines. The records are parsed when they are
+    /// shown, filtered or searched. Multiline rules are not supported.
+    pub fn readfile_lazy(&mut self, filename: &str, tx: mpsc::Sender<TuiEvent>) {
+        let mut lazy = match LazyFile::open(filename) {
+            Ok(lazy) => lazy,
             Err(_error) => panic!("Could not open file={:?}", filename),
         };
-        let mut reader = std::io::BufReader::new(file);
-        let file_size = reader.seek(std::io::SeekFrom::End(0)).unwrap();
-        reader.seek(std::io::SeekFrom::Start(0)).unwrap();
+        // The first record is parsed alone, as some parsers (csv) learn from it.
+        lazy.materialize(0..1, &self.parsers);
 
-        let mut first_line = String::new();
+        // A truncated map can not be read anymore, so it is always dropped.
+        let follow = FollowOptions {
+            reload_on_truncate: true,
+            ..self.follow_options()
+        };
+        let position = FilePosition {
+            offset: lazy.mapped_len() as u64,
+            line_number: Some(lazy.line_count() + 1),
+        };
+        Self::wait_for_changes(filename.to_string(), None, tx, position, follow);
+        self.max_record_size = lazy.max_line_len();
+        self.lazy = Some(lazy);
+    }
 
-        if let Ok(size) = reader.read_line(&mut first_line) {
-            if size > 0 {
-                let mut record = Record::new(first_line);
-                record.set_data("filename", filename.to_string());
-                record.set_data("line_number", "1".to_string());
-                record.parse(&self.parsers);
-                self.all_records.push(record);
+    /// Reads several files, each one with its own rule, into a single timeline ordered
+    /// by the `timestamp` field. Each file is loaded in the background and its batches
+    /// are merged as they arrive, so each file is expected in time order. Records
+    /// without timestamp stay after the previous record of the same file. New lines are
+    /// also inserted in order as they arrive.
+    ///
+    /// With `since` or `until`, only the records between them are read, as in
+    /// `readfile_time_range`, else with `tail` only the end of each file. Compressed and
+    /// not UTF-8 files are read whole.
+    pub fn readfiles_merged(
+        &mut self,
+        files: Vec<(String, SourceRule)>,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        tail: Option<Tail>,
+        tx: mpsc::Sender<TuiEvent>,
+    ) {
+        let filenames: Vec<&str> = files
+            .iter()
+            .map(|(filename, _)| filename.as_str())
+            .collect();
+        let sources = source_names(&filenames);
+        for ((filename, mut rule), source) in files.into_iter().zip(sources) {
+            let mut file = match std::fs::File::open(&filename) {
+                Ok(file) => file,
+                Err(_error) => panic!("Could not open file={:?}", filename),
+            };
+            let total = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+            let compression = Compression::detect(&filename).unwrap_or(None);
+            // Kept, as the byte order of the BOM is needed to follow it
+            let text_start = match compression {
+                Some(_) => 0,
+                None => {
+                    let (encoding, text_start) = file_encoding(&file, rule.encoding);
+                    rule.encoding = encoding;
+                    text_start
+                }
+            };
+            // Only uncompressed UTF-8 files can be searched by time or from the end
+            let window = if compression.is_some() || rule.encoding != Encoding::Utf8 {
+                Ok((0, None))
+            } else if let (None, None, Some(tail)) = (since, until, tail) {
+                tail_start(&mut file, total, tail).map(|start| (start, None))
+            } else {
+                time_window(&file, total, &rule.parsers, since, until)
+            };
+            let (start, end) = match window {
+                Ok(window) => window,
+                Err(error) => panic!("Could not read file={:?}: {}", filename, error),
+            };
+            let position = FilePosition::at(start).in_text(text_start);
+            let start = position.offset;
+            if let Err(error) = file.seek(std::io::SeekFrom::Start(start)) {
+                panic!("Could not read file={:?}: {}", filename, error);
             }
+            let read = Arc::new(AtomicU64::new(start));
+            let counting = CountingReader::new(file, read.clone());
+            let reader: Box<dyn Read + Send> = match (compression, end) {
+                (Some(compression), _) => match compression.decoder(counting) {
+                    Ok(decoder) => decoder,
+                    Err(error) => panic!("Could not decompress file={:?}: {}", filename, error),
+                },
+                (None, Some(end)) => Box::new(counting.take(end - start)),
+                (None, None) => Box::new(counting),
+            };
+
+            let load = Load {
+                filename: filename.clone(),
+                source: Some(source.clone()),
+                encoding: rule.encoding,
+                multiline: rule.multiline.clone(),
+                position,
+                total: end.unwrap_or(total),
+                read,
+                line_count: 0,
+                // Compressed files are not followed, as they are usually rotated logs
+                follow: (compression.is_none() && end.is_none()).then_some(FollowOptions {
+                    encoding: rule.encoding,
+                    ..self.follow
+                }),
+            };
+            load.spawn(
+                std::io::BufReader::new(rule.encoding.reader(reader)),
+                tx.clone(),
+            );
+            self.sources.push(source.clone());
+            self.source_rules.insert(source, rule);
         }
+    }
 
-        let lines: Vec<String> = reader.lines().map(|line| line.unwrap()).collect();
+    /// Creates the records for the lines of a file, joining multiline records if the rule
+    /// asks for it. The first record is parsed alone, as some parsers (csv) learn from it.
+    fn records_from_lines(
+        parsers: &Vec<Parser>,
+        multiline: Option<&MultilineMatcher>,
+        filename: &str,
+        lines: Vec<(FilePosition, String)>,
+    ) -> Vec<Record> {
+        let groups: Vec<(FilePosition, String)> = match multiline {
+            Some(multiline) => {
+                let (positions, lines): (Vec<FilePosition>, Vec<String>) =
+                    lines.into_iter().unzip();
+                multiline
+                    .group_lines(lines)
+                    .into_iter()
+                    .map(|(line, text)| (positions[line - 1], text))
+                    .collect()
+            }
+            None => lines,
+        };
 
-        let records: Vec<Record> = lines
-            .par_iter()
-            .enumerate()
-            .map(|(line_number, line)| {
-                let mut record = Record::new(line.clone());
-                record.set_data("filename", filename.to_string());
-                record.set_data("line_number", (line_number + 1).to_string());
-                record.parse(&self.parsers);
-                record
-            })
-            .collect();
+        let new_record = |(position, line): (FilePosition, String)| {
+            let mut record = Record::new(line);
+            record.set_data("filename", filename.to_string());
+            record.set_source_position(position.line_number, position.offset);
+            record.parse(parsers);
+            record
+        };
 
-        self.visible_records = records.clone();
-        self.all_records.extend(records);
-        self.renumber();
+        let mut groups = groups.into_iter();
+        let mut records: Vec<Record> = groups.next().map(new_record).into_iter().collect();
+        let rest: Vec<(FilePosition, String)> = groups.collect();
+        records.par_extend(rest.into_par_iter().map(new_record));
+        records
+    }
 
-        Self::wait_for_changes(filename.to_string(), tx, file_size.try_into().unwrap());
-        self.max_record_size = self
-            .visible_records
-            .iter()
-            .map(|r| r.original.len())
-            .max()
-            .unwrap_or(0);
+    /// How files of the current rule are followed.
+    fn follow_options(&self) -> FollowOptions {
+        FollowOptions {
+            encoding: self.encoding,
+            ..self.follow
+        }
     }
 
-    pub fn wait_for_changes(filename: String, tx: mpsc::Sender<TuiEvent>, position: usize) {
-        let tx_clone = tx.clone();
-        spawn(move || {
-            let mut position = position;
-            let (tx, rx) = mpsc::channel();
-            let mut watcher = notify::recommended_watcher(tx).unwrap();
+    /// Channel for readers to send new lines. If the rule has multiline settings,
+    /// continuation lines are joined before they reach the application.
+    pub fn record_sender(&self, tx: mpsc::Sender<TuiEvent>) -> mpsc::Sender<TuiEvent> {
+        sender_for(self.multiline.as_ref(), tx)
+    }
+
+    /// Follows the file as `tail -F`: sends the new lines, and when the file is rotated
+    /// (renamed and created again) or truncated, continues from the start of the new one.
+    pub fn wait_for_changes(
+        filename: String,
+        source: Option<String>,
+        tx: mpsc::Sender<TuiEvent>,
+        mut position: FilePosition,
+        options: FollowOptions,
+    ) {
+        // A plain thread, as it blocks for the whole life of the file.
+        thread::spawn(move || {
+            let path = Path::new(&filename);
+            let mut file = match std::fs::File::open(path) {
+                Ok(file) => file,
+                Err(_error) => return,
+            };
+
+            // Watch the directory and not the file, to also see renames and the creation
+            // of the new file.
+            let directory = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            let file_name = path.file_name().map(|name| name.to_os_string());
+            let (watch_tx, rx) = mpsc::channel();
+            let mut watcher = notify::recommended_watcher(watch_tx).unwrap();
             watcher
-                .watch(Path::new(&filename), notify::RecursiveMode::NonRecursive)
+                .watch(&directory, notify::RecursiveMode::NonRecursive)
                 .unwrap();
             loop {
                 match rx.recv() {
-                    Ok(event) => match event.unwrap().kind {
-                        notify::EventKind::Modify(_) => {
-                            position =
-                                Self::read_and_send_new_lines(&filename, &tx_clone, position);
+                    Ok(Ok(event)) => {
+                        if matches!(event.kind, notify::EventKind::Access(_))
+                            || !event
+                                .paths
+                                .iter()
+                                .any(|path| path.file_name() == file_name.as_deref())
+                        {
+                            continue;
                         }
-                        _ => {}
-                    },
-                    Err(e) => println!("watch error: {:?}", e),
+                        position = Self::follow_file(
+                            &filename,
+                            source.as_deref(),
+                            &tx,
+                            &mut file,
+                            position,
+                            options,
+                        );
+                    }
+                    Ok(Err(e)) => println!("watch error: {:?}", e),
+                    Err(_) => return,
                 }
             }
         });
     }
 
-    pub fn read_and_send_new_lines(
+    /// Reads what is new in the followed file. If another file is now at its path, or it
+    /// is shorter than what was read, it continues from the start of the new contents.
+    pub fn follow_file(
         filename: &str,
+        source: Option<&str>,
         tx: &mpsc::Sender<TuiEvent>,
-        position: usize,
-    ) -> usize {
-        let file = std::fs::File::open(filename).expect("could not open file");
-        let mut reader = std::io::BufReader::new(file);
-        let end_position = reader.seek(std::io::SeekFrom::End(0)).unwrap();
-        reader
-            .seek(std::io::SeekFrom::Start(position as u64))
-            .unwrap();
+        file: &mut std::fs::File,
+        position: FilePosition,
+        options: FollowOptions,
+    ) -> FilePosition {
+        // The last lines written to a rotated file before the new one was created
+        let position =
+            Self::read_and_send_new_lines(file, filename, source, tx, position, options.encoding);
+
+        let current = match std::fs::metadata(filename) {
+            Ok(metadata) => metadata,
+            Err(_) => return position, // Rotated, and the new one is not there yet
+        };
+        let opened = match file.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => return position,
+        };
 
-        let mut line_number = 1;
-        for line in reader.lines() {
-            let line = line.expect("could not read line");
-            let mut record = Record::new(line.clone());
-            record.set_data("filename", filename.to_string());
-            record.set_line_number(line_number);
-            tx.send(TuiEvent::NewRecord(record)).unwrap();
-            line_number += 1;
+        if current.ino() != opened.ino() || current.dev() != opened.dev() {
+            *file = match std::fs::File::open(filename) {
+                Ok(file) => file,
+                Err(_) => return position,
+            };
+            if options.separator {
+                Self::send_separator(filename, source, "rotated", tx);
+            }
+        } else if current.len() < position.offset {
+            if options.reload_on_truncate {
+                let _ = tx.send(TuiEvent::ClearRecords(source.map(|s| s.to_string())));
+            } else if options.separator {
+                Self::send_separator(filename, source, "truncated", tx);
+            }
+        } else {
+            return position;
+        }
+
+        let start = FilePosition::START;
+        Self::read_and_send_new_lines(file, filename, source, tx, start, options.encoding)
+    }
+
+    fn send_separator(
+        filename: &str,
+        source: Option<&str>,
+        what: &str,
+        tx: &mpsc::Sender<TuiEvent>,
+    ) {
+        let mut record = Record::new(format!("--- {} {} ---", filename, what));
+        record.set_data("filename", filename.to_string());
+        if let Some(source) = source {
+            record.set_data("source", source.to_string());
+        }
+        record.set_data("mark", "black yellow".into());
+        let _ = tx.send(TuiEvent::NewRecord(record));
+    }
+
+    /// Sends the complete lines after `position`, and returns the position after them.
+    /// A line still being written is left for the next time.
+    pub fn read_and_send_new_lines(
+        mut file: &std::fs::File,
+        filename: &str,
+        source: Option<&str>,
+        tx: &mpsc::Sender<TuiEvent>,
+        position: FilePosition,
+        encoding: Encoding,
+    ) -> FilePosition {
+        // Read again each time, as a rotated file may have another BOM
+        let (encoding, text_start) = file_encoding(file, encoding);
+        let mut position = position.in_text(text_start);
+        if file
+            .seek(std::io::SeekFrom::Start(position.offset))
+            .is_err()
+        {
+            return position;
+        }
+        let mut reader = std::io::BufReader::new(encoding.reader(file));
+
+        let mut buffer = Vec::new();
+        loop {
+            buffer.clear();
+            match reader.read_until(b'\n', &mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(_) if buffer.last() != Some(&b'\n') => break,
+                Ok(_) => {}
+            }
+            let mut record = line_record(&buffer, filename, position);
+            position.advance(line_len(&buffer, encoding));
+            if let Some(source) = source {
+                record.set_data("source", source.to_string());
+            }
+            if tx.send(TuiEvent::NewRecord(record)).is_err() {
+                break;
+            }
         }
 
-        end_position as usize
+        position
     }
 
     pub fn readfile_stdin(&mut self, tx: mpsc::Sender<TuiEvent>) {
+        let tx = self.record_sender(tx);
+        let encoding = self.encoding;
         spawn(move || {
-            let reader = std::io::stdin();
-            let reader = reader.lock();
-            for line in reader.lines() {
-                let line = line.expect("could not read line");
-                let record = Record::new(line);
-                tx.send(TuiEvent::NewRecord(record)).unwrap();
-            }
+            let reader = encoding.reader(std::io::stdin());
+            send_lines(reader, None, encoding, &tx);
         });
     }
 
@@ -197,8 +837,7 @@ impl RecordList {
         mut record: Record,
         lua_engine: Option<&mut crate::lua_engine::LuaEngine>,
     ) {
-        record.parse(&mut self.parsers);
-        record.set_data("line_number", (self.all_records.len() + 1).to_string());
+        record.parse(parsers_for(&self.parsers, &self.source_rules, &record));
 
         // Execute record processors if Lua engine is provided
         if let Some(engine) = lua_engine {
@@ -208,11 +847,153 @@ impl RecordList {
         }
 
         self.max_record_size = self.max_record_size.max(record.original.len());
-        self.all_records.push(record.clone());
 
-        if self.filter.is_none() || record.matches(&self.filter.as_ref().unwrap()) {
-            record.set_line_number(self.visible_records.len() + 1);
-            self.visible_records.push(record);
+        if let Some(lazy) = self.lazy.as_mut() {
+            let visible = self
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(&record));
+            lazy.push(record, visible);
+            return;
+        }
+
+        if self.sources.len() > 1 {
+            self.insert_merged(record);
+        } else {
+            self.all_records.push(record.clone());
+
+            if self
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(&record))
+            {
+                record.index = self.visible_records.len();
+                self.visible_records.push(record);
+            }
+        }
+
+        self.evict();
+    }
+
+    /// Drops the oldest records over `max_records`, except the marked ones if pinned.
+    ///
+    /// Dropping one record moves all the others, so they are dropped in batches of up
+    /// to 1/16 of `max_records` over the limit.
+    fn evict(&mut self) {
+        let max_records = match self.max_records {
+            Some(max_records) => max_records,
+            None => return,
+        };
+        if self.all_records.len() <= max_records + max_records / 16 {
+            return;
+        }
+
+        // Visible records are the all records that pass the filter, in the same order,
+        // but marks are only set in the visible copy.
+        let mut to_drop = self.all_records.len() - max_records;
+        let mut drop_all = vec![false; self.all_records.len()];
+        let mut drop_visible = vec![false; self.visible_records.len()];
+        let mut visible = 0;
+        for (i, record) in self.all_records.iter().enumerate() {
+            if to_drop == 0 {
+                break;
+            }
+            let visible_record = self
+                .visible_records
+                .get(visible)
+                .filter(|visible_record| same_record(visible_record, record));
+            let marked = record.get("mark").is_some()
+                || visible_record.is_some_and(|record| record.get("mark").is_some());
+            if !(self.pin_marks && marked) {
+                drop_all[i] = true;
+                if visible_record.is_some() {
+                    drop_visible[visible] = true;
+                }
+                to_drop -= 1;
+            }
+            if visible_record.is_some() {
+                visible += 1;
+            }
+        }
+
+        let (all_len, visible_len) = (self.all_records.len(), self.visible_records.len());
+        let mut drop_all = drop_all.into_iter();
+        self.all_records.retain(|_| !drop_all.next().unwrap());
+        let mut drop_visible = drop_visible.into_iter();
+        self.visible_records
+            .retain(|_| !drop_visible.next().unwrap());
+
+        self.dropped += all_len - self.all_records.len();
+        self.dropped_visible += visible_len - self.visible_records.len();
+        self.renumber();
+    }
+
+    /// Adds a batch of records of a file being loaded, parsing them in parallel.
+    pub fn add_records(&mut self, mut records: Vec<Record>) {
+        let parsers = &self.parsers;
+        let source_rules = &self.source_rules;
+        // The first record is parsed alone, as some parsers (csv) learn from it.
+        if let Some((first, rest)) = records.split_first_mut() {
+            first.parse(parsers_for(parsers, source_rules, first));
+            rest.par_iter_mut().for_each(|record| {
+                record.parse(parsers_for(parsers, source_rules, record));
+            });
+        }
+
+        let max_record_size = records.iter().map(|r| r.original.len()).max();
+        self.max_record_size = self.max_record_size.max(max_record_size.unwrap_or(0));
+
+        if self.sources.len() > 1 {
+            let visible: Vec<Record> = match &self.filter {
+                Some(filter) => records
+                    .par_iter()
+                    .filter(|record| filter.matches(record))
+                    .cloned()
+                    .collect(),
+                None => records.clone(),
+            };
+            merge_batch(&mut self.all_records, records);
+            let start = merge_batch(&mut self.visible_records, visible);
+            for (i, record) in self.visible_records.iter_mut().enumerate().skip(start) {
+                record.index = i;
+            }
+            self.evict();
+            return;
+        }
+
+        let start = self.visible_records.len();
+        match &self.filter {
+            Some(filter) => self.visible_records.par_extend(
+                records
+                    .par_iter()
+                    .filter(|record| filter.matches(record))
+                    .cloned(),
+            ),
+            None => self.visible_records.extend(records.iter().cloned()),
+        }
+        for (i, record) in self.visible_records.iter_mut().enumerate().skip(start) {
+            record.index = i;
+        }
+        self.all_records.extend(records);
+
+        self.evict();
+    }
+
+    /// Inserts a new record of a merged input in timestamp order. It is usually the last.
+    fn insert_merged(&mut self, record: Record) {
+        let position = merge_position(&self.all_records, &record);
+        self.all_records.insert(position, record.clone());
+
+        if self
+            .filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(&record))
+        {
+            let position = merge_position(&self.visible_records, &record);
+            self.visible_records.insert(position, record);
+            for (i, record) in self.visible_records.iter_mut().enumerate().skip(position) {
+                record.index = i;
+            }
         }
     }
 
@@ -225,33 +1006,18 @@ impl RecordList {
             .spawn()
             .expect("could not execute command");
 
-        let stdout = std::io::BufReader::new(child.stdout.take().expect("could not read stdout"));
-        let stderr = std::io::BufReader::new(child.stderr.take().expect("could not read stderr"));
-        let tx_stdout = tx.clone();
-        let tx_exit = tx.clone();
-        let tx_stderr = tx;
-        spawn(move || {
-            for line in stdout.lines() {
-                if let Ok(line) = line {
-                    let mut record = Record::new(line);
-                    record.set_data("filename", "stdout".into());
-                    tx_stdout.send(TuiEvent::NewRecord(record)).unwrap();
-                } else {
-                    return;
-                }
-            }
-        });
-        spawn(move || {
-            for line in stderr.lines() {
-                if let Ok(line) = line {
-                    let mut record = Record::new(line);
-                    record.set_data("filename", "stderr".into());
-                    tx_stderr.send(TuiEvent::NewRecord(record)).unwrap();
-                } else {
-                    return;
-                }
-            }
-        });
+        let stdout = self
+            .encoding
+            .reader(child.stdout.take().expect("could not read stdout"));
+        let stderr = self
+            .encoding
+            .reader(child.stderr.take().expect("could not read stderr"));
+        let tx_stdout = self.record_sender(tx.clone());
+        let tx_stderr = self.record_sender(tx.clone());
+        let tx_exit = tx;
+        let encoding = self.encoding;
+        spawn(move || send_lines(stdout, Some("stdout"), encoding, &tx_stdout));
+        spawn(move || send_lines(stderr, Some("stderr"), encoding, &tx_stderr));
 
         let child_pid = child.id();
         // wait for the process to finish
@@ -270,6 +1036,68 @@ impl RecordList {
         self.child_process = Some(child_pid);
     }
 
+    /// Receives log messages from the network or a local socket, as a syslog server.
+    /// The address is `udp://host:port`, `tcp://host:port`, `unix:///path` (stream) or
+    /// `unixgram:///path` (datagram, as `/dev/log`). Each record has the sender address
+    /// in the `source` field.
+    pub fn listen(&mut self, address: &str, tx: mpsc::Sender<TuiEvent>) -> std::io::Result<()> {
+        let multiline = self.multiline.clone();
+        let encoding = self.encoding;
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid listen address {:?}", address),
+            )
+        };
+        let (scheme, target) = address.split_once("://").ok_or_else(invalid)?;
+        let address = address.to_string();
+        // Plain threads, as they block for the whole life of the listener.
+        match scheme {
+            "udp" => {
+                let socket = UdpSocket::bind(target)?;
+                let senders = PeerSenders::new(multiline, tx);
+                thread::spawn(move || receive_udp(socket, &address, encoding, senders));
+            }
+            "tcp" => {
+                let listener = TcpListener::bind(target)?;
+                thread::spawn(move || {
+                    for stream in listener.incoming().flatten() {
+                        let source = match stream.peer_addr() {
+                            Ok(peer) => peer.to_string(),
+                            Err(_) => address.clone(),
+                        };
+                        // Each peer joins its own continuation lines
+                        let tx = sender_for(multiline.as_ref(), tx.clone());
+                        let address = address.clone();
+                        let stream = encoding.reader(stream);
+                        thread::spawn(move || receive_stream(stream, &source, &address, tx));
+                    }
+                });
+            }
+            "unix" => {
+                remove_stale_socket(target);
+                let listener = UnixListener::bind(target)?;
+                thread::spawn(move || {
+                    for stream in listener.incoming().flatten() {
+                        let source = unix_source(stream.peer_addr().ok(), &address);
+                        let tx = sender_for(multiline.as_ref(), tx.clone());
+                        let address = address.clone();
+                        let stream = encoding.reader(stream);
+                        thread::spawn(move || receive_stream(stream, &source, &address, tx));
+                    }
+                });
+            }
+            "unixgram" => {
+                remove_stale_socket(target);
+                let socket = UnixDatagram::bind(target)?;
+                let senders = PeerSenders::new(multiline, tx);
+                thread::spawn(move || receive_unix_datagram(socket, &address, encoding, senders));
+            }
+            _ => return Err(invalid()),
+        }
+        Ok(())
+    }
+
     // pub fn filter(&mut self, search: AST) {
     //     let mut result = vec![];
     //     for record in &self.all_records {
@@ -283,10 +1111,16 @@ impl RecordList {
     // }
 
     pub fn filter_parallel(&mut self, search: AST) {
+        let search = Filter::new(search);
+        if let Some(lazy) = self.lazy.as_mut() {
+            lazy.filter(&search, &self.parsers);
+            self.filter = Some(search);
+            return;
+        }
         let result: Vec<Record> = self
             .all_records
             .par_iter()
-            .filter(|record| record.matches(&search))
+            .filter(|record| search.matches(record))
             .map(|record| (*record).clone())
             .collect();
         self.filter = Some(search);
@@ -295,16 +1129,23 @@ impl RecordList {
     }
 
     /// Search for a string in the records, returns the position of the next match.
-    pub fn search_forward(&mut self, search: &AST, start_at: usize) -> Option<usize> {
+    pub fn search_forward(&mut self, search: &Filter, start_at: usize) -> Option<usize> {
+        if let Some(lazy) = &self.lazy {
+            return lazy.search_forward(search, start_at, &self.parsers);
+        }
         for (i, record) in self.all_records.iter().enumerate().skip(start_at) {
-            if record.matches(search) {
+            if search.matches(record) {
                 return Some(i);
             }
         }
         None
     }
 
-    pub fn search_backwards(&mut self, search: &AST, start_at: usize) -> Option<usize> {
+    pub fn search_backwards(&mut self, search: &Filter, start_at: usize) -> Option<usize> {
+        if let Some(lazy) = &self.lazy {
+            let end_at = if start_at == 0 { lazy.len() } else { start_at };
+            return lazy.search_backwards(search, end_at, &self.parsers);
+        }
         let rstart_at = if start_at == 0 {
             self.all_records.len()
         } else {
@@ -313,7 +1154,7 @@ impl RecordList {
 
         for pos in (0..rstart_at).rev() {
             let record = &self.all_records[pos];
-            if record.matches(search) {
+            if search.matches(record) {
                 return Some(pos);
             }
         }
@@ -327,25 +1168,55 @@ impl RecordList {
     }
 
     pub fn reparse(&mut self) {
+        if let Some(lazy) = self.lazy.as_mut() {
+            lazy.reparse(&self.parsers);
+            return;
+        }
+        let parsers = &self.parsers;
+        let source_rules = &self.source_rules;
         self.all_records.par_iter_mut().for_each(|record| {
-            record.parse(&self.parsers);
+            record.parse(parsers_for(parsers, source_rules, record));
         });
         self.visible_records.par_iter_mut().for_each(|record| {
-            record.parse(&self.parsers);
+            record.parse(parsers_for(parsers, source_rules, record));
         });
         self.renumber();
     }
 
     pub fn len(&self) -> usize {
+        if let Some(lazy) = &self.lazy {
+            return lazy.len();
+        }
         return self.visible_records.len();
     }
 
     pub fn clear(&mut self) {
+        self.lazy = None;
+        self.older = None;
+        self.dropped = 0;
         self.all_records.clear();
         self.visible_records.clear();
     }
 
+    /// Removes the records of a merged source, or all if None.
+    pub fn clear_source(&mut self, source: Option<&str>) {
+        match source {
+            Some(source) => {
+                let other_source =
+                    |record: &Record| record.get("source").map(|s| s.as_str()) != Some(source);
+                self.all_records.retain(other_source);
+                self.visible_records.retain(other_source);
+                self.renumber();
+            }
+            None => self.clear(),
+        }
+    }
+
+    /// The visible record at the index. On lazy files, only if it was materialized.
     pub fn get(&self, index: usize) -> Option<&Record> {
+        if let Some(lazy) = &self.lazy {
+            return lazy.get(index);
+        }
         if index < self.visible_records.len() {
             Some(&self.visible_records[index])
         } else {
@@ -353,6 +1224,21 @@ impl RecordList {
         }
     }
 
+    /// The visible record at the index, to change it. Lazy files parse it if needed.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Record> {
+        match self.lazy.as_mut() {
+            Some(lazy) => lazy.get_mut(index, &self.parsers),
+            None => self.visible_records.get_mut(index),
+        }
+    }
+
+    /// Parses the records in the range of lazy files, before they are shown.
+    pub fn materialize(&mut self, range: Range<usize>) {
+        if let Some(lazy) = self.lazy.as_mut() {
+            lazy.materialize(range, &self.parsers);
+        }
+    }
+
     pub fn max_record_size(&self, key: &str) -> usize {
         if self.max_record_size > 0 {
             return self.max_record_size;
@@ -375,21 +1261,543 @@ impl Drop for RecordList {
     }
 }
 
+/// Largest message received, as the largest UDP datagram. Octet counted messages
+/// that claim to be longer are read as newline delimited, so a peer can not make
+/// it allocate any size.
+const MAX_MESSAGE_SIZE: usize = 65536;
+/// Digits of the longest octet count, `MAX_MESSAGE_SIZE`.
+const MAX_LENGTH_DIGITS: usize = 5;
+
+/// Sends a record for each line of a received message.
+fn send_message(message: &[u8], source: &str, address: &str, tx: &mpsc::Sender<TuiEvent>) -> bool {
+    let lines = message.split(|byte| *byte == b'\n').map(trim_line_end);
+    for line in lines.filter(|line| !line.is_empty()) {
+        let mut record = Record::from_bytes(line);
+        record.set_data("filename", address.to_string());
+        record.set_data("source", source.to_string());
+        if tx.send(TuiEvent::NewRecord(record)).is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+/// A sender for each peer of a datagram socket, so each one joins its own continuation
+/// lines. Unnamed unix clients can not be told apart, and share one.
+struct PeerSenders {
+    multiline: Option<MultilineMatcher>,
+    tx: mpsc::Sender<TuiEvent>,
+    senders: HashMap<String, mpsc::Sender<TuiEvent>>,
+}
+
+impl PeerSenders {
+    fn new(multiline: Option<MultilineMatcher>, tx: mpsc::Sender<TuiEvent>) -> PeerSenders {
+        PeerSenders {
+            multiline,
+            tx,
+            senders: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, peer: &str) -> &mpsc::Sender<TuiEvent> {
+        self.senders
+            .entry(peer.to_string())
+            .or_insert_with(|| sender_for(self.multiline.as_ref(), self.tx.clone()))
+    }
+}
+
+fn receive_udp(socket: UdpSocket, address: &str, encoding: Encoding, mut senders: PeerSenders) {
+    let mut buffer = vec![0; MAX_MESSAGE_SIZE];
+    while let Ok((size, peer)) = socket.recv_from(&mut buffer) {
+        let source = peer.to_string();
+        let message = decode_message(&buffer[..size], encoding);
+        if !send_message(&message, &source, address, senders.get(&source)) {
+            return;
+        }
+    }
+}
+
+fn receive_unix_datagram(
+    socket: UnixDatagram,
+    address: &str,
+    encoding: Encoding,
+    mut senders: PeerSenders,
+) {
+    let mut buffer = vec![0; MAX_MESSAGE_SIZE];
+    while let Ok((size, peer)) = socket.recv_from(&mut buffer) {
+        let source = unix_source(Some(peer), address);
+        let message = decode_message(&buffer[..size], encoding);
+        if !send_message(&message, &source, address, senders.get(&source)) {
+            return;
+        }
+    }
+}
+
+/// A datagram in the rule encoding, as UTF-8.
+fn decode_message(message: &[u8], encoding: Encoding) -> std::borrow::Cow<'_, [u8]> {
+    if encoding == Encoding::Utf8 {
+        return message.into();
+    }
+    let mut decoded = Vec::new();
+    let _ = encoding.reader(message).read_to_end(&mut decoded);
+    decoded.into()
+}
+
+/// Reads the messages of a stream connection. They are newline delimited, or octet
+/// counted as syslog over TCP (RFC 6587): `<length> <message>`.
+fn receive_stream(stream: impl Read, source: &str, address: &str, tx: mpsc::Sender<TuiEvent>) {
+    let mut reader = std::io::BufReader::new(stream);
+    let mut buffer = Vec::new();
+    let mut byte = [0];
+    loop {
+        buffer.clear();
+        // The octet count is only looked for in the first bytes, up to one that is not
+        // a digit, so a newline delimited message is never held waiting for a space.
+        while buffer.len() <= MAX_LENGTH_DIGITS && buffer.iter().all(u8::is_ascii_digit) {
+            match reader.read(&mut byte) {
+                Ok(1) => buffer.push(byte[0]),
+                _ => {
+                    send_message(&buffer, source, address, &tx);
+                    return;
+                }
+            }
+        }
+        if let Some((b' ', digits)) = buffer.split_last() {
+            let length = std::str::from_utf8(digits)
+                .ok()
+                .and_then(|length| length.parse::<usize>().ok())
+                .filter(|length| *length <= MAX_MESSAGE_SIZE);
+            let is_syslog = matches!(reader.fill_buf(), Ok(data) if data.first() == Some(&b'<'));
+            if let (Some(length), true) = (length, is_syslog) {
+                buffer.resize(length, 0);
+                if reader.read_exact(&mut buffer).is_err() {
+                    return;
+                }
+                if !send_message(&buffer, source, address, &tx) {
+                    return;
+                }
+                continue;
+            }
+        }
+        // Newline delimited, maybe already read up to its end
+        if buffer.last() != Some(&b'\n') && reader.read_until(b'\n', &mut buffer).is_err() {
+            return;
+        }
+        if !send_message(&buffer, source, address, &tx) {
+            return;
+        }
+    }
+}
+
+/// Unix socket clients are usually unnamed, then the listen address is the source.
+fn unix_source(peer: Option<std::os::unix::net::SocketAddr>, address: &str) -> String {
+    match peer.as_ref().and_then(|peer| peer.as_pathname()) {
+        Some(path) => path.to_string_lossy().to_string(),
+        None => address.to_string(),
+    }
+}
+
+/// Removes the socket file left by a previous run, so it can be bound again. Other
+/// files are kept, and bind fails.
+fn remove_stale_socket(path: &str) {
+    use std::os::unix::fs::FileTypeExt;
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn sender_for(
+    multiline: Option<&MultilineMatcher>,
+    tx: mpsc::Sender<TuiEvent>,
+) -> mpsc::Sender<TuiEvent> {
+    match multiline {
+        Some(multiline) => multiline.spawn_assembler(tx),
+        None => tx,
+    }
+}
+
+/// Where the part of the file to load starts, at a line start.
+fn tail_start(file: &mut std::fs::File, size: u64, tail: Tail) -> std::io::Result<u64> {
+    match tail {
+        Tail::Lines(lines) => lines_start(file, size, lines),
+        Tail::Bytes(bytes) if bytes >= size => Ok(0),
+        Tail::Bytes(bytes) => next_line_start(file, size - bytes),
+    }
+}
+
+/// Start of the first line at or after `position`.
+fn next_line_start(mut file: &std::fs::File, position: u64) -> std::io::Result<u64> {
+    if position == 0 {
+        return Ok(0);
+    }
+    file.seek(std::io::SeekFrom::Start(position - 1))?;
+    let mut line = Vec::new();
+    let read = std::io::BufReader::new(file).read_until(b'\n', &mut line)?;
+    Ok(position - 1 + read as u64)
+}
+
+/// The first line with a timestamp at or after `position`: its start and timestamp.
+/// Only some lines are looked at, so a long run of lines without timestamp is as the
+/// end of the file.
+fn timestamp_after(
+    mut file: &std::fs::File,
+    position: u64,
+    parsers: &Vec<Parser>,
+) -> std::io::Result<Option<(u64, DateTime<Utc>)>> {
+    let mut start = next_line_start(file, position)?;
+    file.seek(std::io::SeekFrom::Start(start))?;
+    let mut reader = std::io::BufReader::new(file);
+    let mut buffer = Vec::new();
+    for _ in 0..TIME_SEARCH_LINES {
+        buffer.clear();
+        let read = reader.read_until(b'\n', &mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        let mut record = Record::from_bytes(trim_line_end(&buffer));
+        record.parse(parsers);
+        if let Some(timestamp) = record_timestamp(&record) {
+            return Ok(Some((start, timestamp)));
+        }
+        start += read as u64;
+    }
+    Ok(None)
+}
+
+/// Where the lines between `since` and `until` start and end in a file sorted by time.
+/// No end if there is no `until`.
+fn time_window(
+    file: &std::fs::File,
+    total: u64,
+    parsers: &Vec<Parser>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+) -> std::io::Result<(u64, Option<u64>)> {
+    let start = match since {
+        Some(since) => time_position(file, total, parsers, |time| time >= since)?,
+        None => 0,
+    };
+    let end = match until {
+        Some(until) => Some(time_position(file, total, parsers, |time| time > until)?),
+        None => None,
+    };
+    Ok((start, end))
+}
+
+/// Start of the first line whose timestamp is `after`, with a binary search on a file
+/// sorted by time. Lines without timestamp before it are of the previous record.
+fn time_position(
+    file: &std::fs::File,
+    size: u64,
+    parsers: &Vec<Parser>,
+    after: impl Fn(DateTime<Utc>) -> bool,
+) -> std::io::Result<u64> {
+    let (mut low, mut high) = (0, size);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        match timestamp_after(file, middle, parsers)? {
+            Some((_, timestamp)) if !after(timestamp) => low = middle + 1,
+            _ => high = middle,
+        }
+    }
+    Ok(timestamp_after(file, low, parsers)?.map_or(size, |(start, _)| start))
+}
+
+/// Start of the first of the last `lines` lines before `end`, looking backwards for the
+/// line ends. The line end just before `end` ends the last line.
+fn lines_start(file: &mut std::fs::File, end: u64, lines: usize) -> std::io::Result<u64> {
+    if lines == 0 {
+        return Ok(end);
+    }
+    let mut found = 0;
+    let mut chunk_end = end;
+    let mut buffer = vec![0; TAIL_CHUNK_SIZE as usize];
+    while chunk_end > 0 {
+        let chunk_start = chunk_end.saturating_sub(TAIL_CHUNK_SIZE);
+        let chunk = &mut buffer[..(chunk_end - chunk_start) as usize];
+        file.seek(std::io::SeekFrom::Start(chunk_start))?;
+        file.read_exact(chunk)?;
+        for (i, byte) in chunk.iter().enumerate().rev() {
+            let position = chunk_start + i as u64;
+            if *byte == b'\n' && position + 1 != end {
+                found += 1;
+                if found == lines {
+                    return Ok(position + 1);
+                }
+            }
+        }
+        chunk_end = chunk_start;
+    }
+    Ok(0)
+}
+
+/// The line without the line end.
+fn trim_line_end(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// The encoding of a file, with the byte order of its BOM if it has one, and where its
+/// text starts after the BOM.
+fn file_encoding(file: &std::fs::File, encoding: Encoding) -> (Encoding, u64) {
+    let mut start = [0; 2];
+    match file.read_at(&mut start, 0) {
+        Ok(2) => {
+            let (encoding, bom) = encoding.with_bom(&start);
+            (encoding, bom as u64)
+        }
+        _ => (encoding, 0),
+    }
+}
+
+/// Size in the source of a line read through the `encoding` reader.
+fn line_len(buffer: &[u8], encoding: Encoding) -> u64 {
+    match encoding {
+        Encoding::Utf8 => buffer.len() as u64,
+        _ => encoding.encoded_len(&String::from_utf8_lossy(buffer)) as u64,
+    }
+}
+
+/// Reads all the lines, with where each one starts. Invalid UTF-8 is replaced, and
+/// reading stops at the first error.
+fn read_lines(
+    reader: impl Read,
+    mut position: FilePosition,
+    encoding: Encoding,
+) -> Vec<(FilePosition, String)> {
+    let mut reader = std::io::BufReader::new(reader);
+    let mut lines = Vec::new();
+    let mut buffer = Vec::new();
+    while matches!(reader.read_until(b'\n', &mut buffer), Ok(1..)) {
+        let line = String::from_utf8_lossy(trim_line_end(&buffer)).to_string();
+        lines.push((position, line));
+        position.advance(line_len(&buffer, encoding));
+        buffer.clear();
+    }
+    lines
+}
+
+/// Sends a record for each line, until the end of the input or an error. Positions
+/// are counted from the start of the stream.
+fn send_lines(
+    reader: impl Read,
+    filename: Option<&str>,
+    encoding: Encoding,
+    tx: &mpsc::Sender<TuiEvent>,
+) {
+    let mut reader = std::io::BufReader::new(reader);
+    let mut position = FilePosition::START;
+    let mut buffer = Vec::new();
+    while matches!(reader.read_until(b'\n', &mut buffer), Ok(1..)) {
+        let mut record = Record::from_bytes(trim_line_end(&buffer));
+        record.set_source_position(position.line_number, position.offset);
+        if let Some(filename) = filename {
+            record.set_data("filename", filename.to_string());
+        }
+        if tx.send(TuiEvent::NewRecord(record)).is_err() {
+            return;
+        }
+        position.advance(line_len(&buffer, encoding));
+        buffer.clear();
+    }
+}
+
+/// A record for a line read from a file, without the line end.
+fn line_record(buffer: &[u8], filename: &str, position: FilePosition) -> Record {
+    let mut record = Record::from_bytes(trim_line_end(buffer));
+    record.set_data("filename", filename.to_string());
+    record.set_source_position(position.line_number, position.offset);
+    record
+}
+
+/// Counts the bytes read, for the loading progress. Under the decoder on compressed files.
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R> CountingReader<R> {
+    fn new(inner: R, count: Arc<AtomicU64>) -> CountingReader<R> {
+        CountingReader { inner, count }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+/// Whether both are copies of the same record, one of them maybe marked.
+fn same_record(a: &Record, b: &Record) -> bool {
+    a.original == b.original
+        && a.line_number == b.line_number
+        && a.byte_offset == b.byte_offset
+        && a.get("source") == b.get("source")
+}
+
+/// The parsers of the rule of the record source, or the current rule ones.
+fn parsers_for<'a>(
+    parsers: &'a Vec<Parser>,
+    source_rules: &'a HashMap<String, SourceRule>,
+    record: &Record,
+) -> &'a Vec<Parser> {
+    match record
+        .get("source")
+        .and_then(|source| source_rules.get(source))
+    {
+        Some(rule) => &rule.parsers,
+        None => parsers,
+    }
+}
+
+/// Names for the `source` field of merged files: the file name, to keep it short,
+/// with as many directories as needed to tell apart files with the same name. The
+/// same file given again gets `#2`, `#3`...
+fn source_names(filenames: &[&str]) -> Vec<String> {
+    let components: Vec<Vec<String>> = filenames
+        .iter()
+        .map(|filename| {
+            Path::new(filename)
+                .components()
+                .filter(|component| matches!(component, std::path::Component::Normal(_)))
+                .map(|component| component.as_os_str().to_string_lossy().to_string())
+                .collect()
+        })
+        .collect();
+    let suffix = |components: &[String], depth: usize| {
+        components[components.len().saturating_sub(depth)..].join("/")
+    };
+
+    let mut depths = vec![1; filenames.len()];
+    let mut names: Vec<String> = components.iter().map(|c| suffix(c, 1)).collect();
+    loop {
+        let mut longer = false;
+        for (i, name) in names.iter().enumerate() {
+            let repeated = names
+                .iter()
+                .enumerate()
+                .any(|(j, other)| j != i && other == name);
+            if repeated && depths[i] < components[i].len() {
+                depths[i] += 1;
+                longer = true;
+            }
+        }
+        if !longer {
+            break;
+        }
+        names = components
+            .iter()
+            .zip(&depths)
+            .map(|(components, depth)| suffix(components, *depth))
+            .collect();
+    }
+
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    names
+        .into_iter()
+        .map(|name| {
+            let count = seen.entry(name.clone()).or_insert(0);
+            *count += 1;
+            match *count {
+                1 => name,
+                count => format!("{}#{}", name, count),
+            }
+        })
+        .collect()
+}
+
+fn record_timestamp(record: &Record) -> Option<DateTime<Utc>> {
+    match record.get_value("timestamp") {
+        Some(Value::Timestamp(timestamp)) => Some(*timestamp),
+        _ => record
+            .get("timestamp")
+            .and_then(|timestamp| value::parse_timestamp(timestamp)),
+    }
+}
+
+/// Merges a batch of records of one source, in file order, into a timeline. Returns
+/// where the timeline changed.
+fn merge_batch(records: &mut Vec<Record>, batch: Vec<Record>) -> usize {
+    let start = match batch.first() {
+        Some(first) => merge_position(records, first),
+        None => return records.len(),
+    };
+    let mut tail = records.split_off(start).into_iter().peekable();
+    for record in batch {
+        // After all the records with a lower or equal timestamp, as `merge_position`
+        if let Some(timestamp) = record_timestamp(&record) {
+            while let Some(next) = tail.next_if(|next| {
+                record_timestamp(next).is_none_or(|next_timestamp| next_timestamp <= timestamp)
+            }) {
+                records.push(next);
+            }
+        }
+        records.push(record);
+    }
+    records.extend(tail);
+    start
+}
+
+/// Where a new record goes in a timeline: after all the records with a lower or equal
+/// timestamp. Records without timestamp go after the last one of the same source.
+fn merge_position(records: &[Record], record: &Record) -> usize {
+    match record_timestamp(record) {
+        Some(timestamp) => {
+            let mut position = records.len();
+            for (i, other) in records.iter().enumerate().rev() {
+                match record_timestamp(other) {
+                    Some(other_timestamp) if other_timestamp > timestamp => position = i,
+                    Some(_) => break,
+                    None => {}
+                }
+            }
+            position
+        }
+        None => records
+            .iter()
+            .rposition(|other| other.get("source") == record.get("source"))
+            .map(|i| i + 1)
+            .unwrap_or(records.len()),
+    }
+}
+
 pub fn load_parsers(
     rule: &RulesSettings,
+    patterns: &HashMap<String, String>,
     parsers: &mut Vec<parser::Parser>,
 ) -> Result<(), parser::ParserError> {
     for extractor in rule.extractors.iter() {
-        parsers.push(parser::Parser::new(extractor)?);
+        parsers.push(parser::Parser::new_with_patterns(extractor, patterns)?);
     }
 
     Ok(())
 }
 
+pub fn load_encoding(rule: &RulesSettings) -> Result<Encoding, parser::ParserError> {
+    rule.encoding
+        .as_deref()
+        .map_or(Ok(Encoding::Utf8), Encoding::new)
+}
+
+pub fn load_multiline(
+    rule: &RulesSettings,
+) -> Result<Option<MultilineMatcher>, parser::ParserError> {
+    rule.multiline
+        .as_ref()
+        .map(MultilineMatcher::new)
+        .transpose()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::settings::RulesSettings;
+    use chrono::TimeZone;
 
     #[test]
     fn test_load_parsers_with_transforms() {
@@ -406,10 +1814,13 @@ mod tests {
             ],
             filters: vec![],
             columns: vec![],
+            multiline: None,
+            max_records: None,
+            encoding: None,
         };
 
         let mut parsers = Vec::new();
-        let result = load_parsers(&rule, &mut parsers);
+        let result = load_parsers(&rule, &HashMap::new(), &mut parsers);
 
         // Should succeed without errors
         assert!(result.is_ok());
@@ -453,10 +1864,13 @@ mod tests {
             extractors: vec!["logfmt".to_string(), "invalid_parser_type".to_string()],
             filters: vec![],
             columns: vec![],
+            multiline: None,
+            max_records: None,
+            encoding: None,
         };
 
         let mut parsers = Vec::new();
-        let result = load_parsers(&rule, &mut parsers);
+        let result = load_parsers(&rule, &HashMap::new(), &mut parsers);
 
         // Should fail with InvalidParser error
         assert!(result.is_err());
@@ -479,10 +1893,13 @@ mod tests {
             ],
             filters: vec![],
             columns: vec![],
+            multiline: None,
+            max_records: None,
+            encoding: None,
         };
 
         let mut parsers = Vec::new();
-        let result = load_parsers(&rule, &mut parsers);
+        let result = load_parsers(&rule, &HashMap::new(), &mut parsers);
 
         // Should succeed without errors
         assert!(result.is_ok());
@@ -502,6 +1919,946 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_records_from_lines_multiline() {
+        let rule = RulesSettings {
+            name: "test".to_string(),
+            file_patterns: vec![],
+            extractors: vec!["regex (?P<level>ERROR|INFO)".to_string()],
+            filters: vec![],
+            columns: vec![],
+            multiline: Some(crate::settings::MultilineSettings {
+                start: Some(r"^\d{4}-\d{2}-\d{2}".to_string()),
+                continuation: None,
+                timeout_ms: 200,
+            }),
+            max_records: None,
+            encoding: None,
+        };
+        let mut records = RecordList::new();
+        load_parsers(&rule, &HashMap::new(), &mut records.parsers).unwrap();
+        records.multiline = load_multiline(&rule).unwrap();
+
+        let content = "2024-01-01 ERROR boom\n\
+            Traceback (most recent call last):\n  \
+            File \"app.py\", line 1\n\
+            2024-01-01 INFO ok\n";
+        let lines = read_lines(content.as_bytes(), FilePosition::START, Encoding::Utf8);
+        let result = RecordList::records_from_lines(
+            &records.parsers,
+            records.multiline.as_ref(),
+            "app.log",
+            lines,
+        );
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].original.lines().count(), 3);
+        assert_eq!(result[0].get("level"), Some(&"ERROR".to_string()));
+        assert_eq!(result[0].get("line_number"), Some(&"1".to_string()));
+        assert_eq!(result[1].get("line_number"), Some(&"4".to_string()));
+        assert_eq!(result[1].byte_offset, Some(81));
+        assert!(Filter::new(crate::ast::parse("Traceback").unwrap()).matches(&result[0]));
+    }
+
+    fn write_gz(path: &Path, content: &str) {
+        use std::io::Write;
+        let file = std::fs::File::create(path).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        encoder.write_all(content.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_readfiles_merged() {
+        let dir = std::env::temp_dir().join(format!("tailtales_merge_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Compressed, so they are not followed
+        let api = dir.join("api.log.gz");
+        let db = dir.join("db.log.gz");
+        write_gz(
+            &api,
+            "timestamp=2024-01-01T10:00:00Z msg=request\n  continuation\ntimestamp=2024-01-01T10:00:02Z msg=response\n",
+        );
+        write_gz(
+            &db,
+            "2024-01-01 10:00:01 query\n2024-01-01 10:00:03 commit\n",
+        );
+
+        let logfmt = SourceRule {
+            parsers: vec![Parser::new("logfmt").unwrap()],
+            ..SourceRule::default()
+        };
+        let regex = SourceRule {
+            parsers: vec![Parser::new(
+                r"regex (?P<timestamp>\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}) (?P<msg>.*)",
+            )
+            .unwrap()],
+            ..SourceRule::default()
+        };
+
+        let mut records = RecordList::new();
+        let (tx, rx) = mpsc::channel();
+        records.readfiles_merged(
+            vec![
+                (api.to_string_lossy().to_string(), logfmt),
+                (db.to_string_lossy().to_string(), regex),
+            ],
+            None,
+            None,
+            None,
+            tx,
+        );
+        // Loaded in the background, each file merged as its batches arrive
+        assert!(records.all_records.is_empty());
+        let mut lines = Vec::new();
+        for _ in 0..2 {
+            let progress = load_events(&mut records, &rx);
+            // Decompressed as it is read, the progress is of the compressed file
+            assert_eq!(progress.read, progress.total);
+            lines.push(progress.lines);
+        }
+        lines.sort();
+        assert_eq!(lines, vec![2, 3]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let lines: Vec<(&str, &str)> = records
+            .visible_records
+            .iter()
+            .map(|r| (r.get("source").unwrap().as_str(), r.original.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ("api.log.gz", "timestamp=2024-01-01T10:00:00Z msg=request"),
+                ("api.log.gz", "  continuation"),
+                ("db.log.gz", "2024-01-01 10:00:01 query"),
+                ("api.log.gz", "timestamp=2024-01-01T10:00:02Z msg=response"),
+                ("db.log.gz", "2024-01-01 10:00:03 commit"),
+            ]
+        );
+        assert_eq!(records.sources, vec!["api.log.gz", "db.log.gz"]);
+        // Each source is parsed with its own rule
+        assert_eq!(
+            records.visible_records[2].get("msg"),
+            Some(&"query".to_string())
+        );
+
+        // New lines are inserted in order, and parsed with their source rule
+        let mut record = Record::new("2024-01-01 10:00:02 late insert".to_string());
+        record.set_data("source", "db.log.gz".to_string());
+        records.add_record(record, None);
+        assert_eq!(
+            records.visible_records[4].original,
+            "2024-01-01 10:00:02 late insert"
+        );
+        assert_eq!(records.visible_records[4].index, 4);
+        assert_eq!(records.visible_records[5].index, 5);
+
+        let mut record = Record::new("no timestamp".to_string());
+        record.set_data("source", "api.log.gz".to_string());
+        records.add_record(record, None);
+        assert_eq!(records.visible_records[4].original, "no timestamp");
+    }
+
+    #[test]
+    fn test_merge_batch() {
+        let batch = |source: &str, lines: &[&str]| -> Vec<Record> {
+            lines
+                .iter()
+                .map(|line| {
+                    let mut record = Record::new(line.to_string());
+                    record.set_data("source", source.to_string());
+                    if let Some((timestamp, _)) = line.split_once(' ') {
+                        record.set_data("timestamp", timestamp.to_string());
+                    }
+                    record
+                })
+                .collect()
+        };
+        let mut timeline = batch("a", &["2024-01-01T10:00:01Z a1", "2024-01-01T10:00:03Z a3"]);
+        let start = merge_batch(
+            &mut timeline,
+            batch(
+                "b",
+                &["2024-01-01T10:00:00Z b0", "2024-01-01T10:00:02Z b2", "b"],
+            ),
+        );
+        assert_eq!(start, 0);
+        let start = merge_batch(&mut timeline, batch("a", &["a", "2024-01-01T10:00:04Z a4"]));
+        assert_eq!(start, 5);
+        let lines: Vec<&str> = timeline.iter().map(|r| r.original.as_str()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "2024-01-01T10:00:00Z b0",
+                "2024-01-01T10:00:01Z a1",
+                "2024-01-01T10:00:02Z b2",
+                "b",
+                "2024-01-01T10:00:03Z a3",
+                "a",
+                "2024-01-01T10:00:04Z a4",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_readfiles_merged_same_name() {
+        let dir = std::env::temp_dir().join(format!("tailtales_same_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("a")).unwrap();
+        std::fs::create_dir_all(dir.join("b")).unwrap();
+        let (a, b) = (dir.join("a/app.log.gz"), dir.join("b/app.log.gz"));
+        write_gz(&a, "2024-01-01T10:00:00Z a\n");
+        write_gz(&b, "2024-01-01T10:00:01Z b\n");
+
+        let rule = |field: &str| SourceRule {
+            parsers: vec![
+                Parser::new(&format!(r"regex (?P<timestamp>\S+) (?P<{}>.*)", field)).unwrap(),
+            ],
+            ..SourceRule::default()
+        };
+        let mut records = RecordList::new();
+        let (tx, rx) = mpsc::channel();
+        records.readfiles_merged(
+            vec![
+                (a.to_string_lossy().to_string(), rule("in_a")),
+                (b.to_string_lossy().to_string(), rule("in_b")),
+            ],
+            None,
+            None,
+            None,
+            tx,
+        );
+        load_events(&mut records, &rx);
+        load_events(&mut records, &rx);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(records.sources, vec!["a/app.log.gz", "b/app.log.gz"]);
+        // Each one parsed with its own rule
+        assert_eq!(records.visible_records[0].get("in_a").unwrap(), "a");
+        assert_eq!(records.visible_records[1].get("in_b").unwrap(), "b");
+
+        assert_eq!(
+            source_names(&["x/app.log", "y/x/app.log", "db.log", "db.log", "/db.log"]),
+            vec!["x/app.log", "y/x/app.log", "db.log", "db.log#2", "db.log#3"]
+        );
+    }
+
+    fn received_lines(rx: &mpsc::Receiver<TuiEvent>) -> Vec<String> {
+        rx.try_iter()
+            .map(|event| match event {
+                TuiEvent::NewRecord(record) => record.original,
+                TuiEvent::ClearRecords(_) => "<clear>".to_string(),
+                _ => panic!("Unexpected event"),
+            })
+            .collect()
+    }
+
+    /// Adds the records loaded in the background, until done.
+    fn load_events(records: &mut RecordList, rx: &mpsc::Receiver<TuiEvent>) -> LoadProgress {
+        loop {
+            match rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+                TuiEvent::NewRecords(batch) => records.add_records(batch),
+                TuiEvent::LoadProgress(progress) if progress.done => return progress,
+                TuiEvent::LoadProgress(_) => {}
+                _ => panic!("Unexpected event"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_readfile_parallel_multiline() {
+        let path = std::env::temp_dir().join(format!("tailtales_load_{}.log", std::process::id()));
+        std::fs::write(
+            &path,
+            "level=info first\n  continuation\nlevel=error second",
+        )
+        .unwrap();
+
+        let mut records = RecordList::new();
+        records.parsers = vec![Parser::new("logfmt").unwrap()];
+        records.multiline = Some(
+            MultilineMatcher::new(&crate::settings::MultilineSettings {
+                start: None,
+                continuation: Some(r"^\s".to_string()),
+                timeout_ms: 50,
+            })
+            .unwrap(),
+        );
+        records.filter_parallel(crate::ast::parse("level == \"error\"").unwrap());
+        let (tx, rx) = mpsc::channel();
+        records.readfile_parallel(path.to_str().unwrap(), None, tx);
+        let progress = load_events(&mut records, &rx);
+        assert_eq!(progress.lines, 3);
+
+        assert_eq!(records.all_records.len(), 2);
+        assert_eq!(
+            records.all_records[0].original,
+            "level=info first\n  continuation"
+        );
+        assert_eq!(records.len(), 1);
+        assert_eq!(records.get(0).unwrap().get("line_number").unwrap(), "3");
+        assert_eq!(records.get(0).unwrap().index, 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_readfile_compressed() {
+        // Detected by content, whatever the extension
+        let path = std::env::temp_dir().join(format!("tailtales_zstd_{}.log", std::process::id()));
+        std::fs::write(
+            &path,
+            zstd::encode_all("one\ntwo\r\nthree".as_bytes(), 0).unwrap(),
+        )
+        .unwrap();
+        let filename = path.to_str().unwrap();
+        let compression = Compression::detect(filename).unwrap().unwrap();
+
+        let mut records = RecordList::new();
+        let (tx, rx) = mpsc::channel();
+        records.readfile_compressed(filename, compression, tx);
+        // The first line is read before returning
+        assert_eq!(records.len(), 1);
+        let progress = load_events(&mut records, &rx);
+        assert_eq!(progress.lines, 3);
+        assert_eq!(progress.read, progress.total);
+
+        let records = &records.visible_records;
+        let lines: Vec<&str> = records.iter().map(|r| r.original.as_str()).collect();
+        assert_eq!(lines, vec!["one", "two", "three"]);
+        assert_eq!(records[2].get("line_number").unwrap(), "3");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_readfile_invalid_utf8_and_encoding() {
+        let path = std::env::temp_dir().join(format!("tailtales_bytes_{}.log", std::process::id()));
+        std::fs::write(&path, b"caf\xe9\n\x01binary\xff\n").unwrap();
+        let filename = path.to_str().unwrap();
+
+        // Invalid bytes are replaced, and the line bytes kept
+        let mut records = RecordList::new();
+        let (tx, rx) = mpsc::channel();
+        records.readfile_parallel(filename, None, tx);
+        load_events(&mut records, &rx);
+        let lines = &records.visible_records;
+        assert_eq!(lines[0].original, "caf\u{fffd}");
+        assert_eq!(lines[0].original_bytes(), b"caf\xe9");
+        assert_eq!(lines[1].original, "\x01binary\u{fffd}");
+        assert_eq!(lines[1].original_bytes(), b"\x01binary\xff");
+
+        // Latin-1 is transcoded
+        let mut records = RecordList::new();
+        records.encoding = Encoding::Latin1;
+        let (tx, rx) = mpsc::channel();
+        records.readfile_parallel(filename, None, tx);
+        load_events(&mut records, &rx);
+        let lines = &records.visible_records;
+        assert_eq!(lines[0].original, "café");
+        assert_eq!(lines[0].bytes, None);
+        assert_eq!(lines[1].original, "\x01binary\u{ff}");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_readfile_utf16_bom() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("tailtales_bom_{}.log", std::process::id()));
+        let be =
+            |text: &str| -> Vec<u8> { text.encode_utf16().flat_map(|u| u.to_be_bytes()).collect() };
+        let mut content = vec![0xfe, 0xff];
+        content.extend(be("a\n"));
+        // A lone low surrogate
+        content.extend([0xdc, 0x00]);
+        content.extend(be("b\n"));
+        std::fs::write(&path, &content).unwrap();
+        let filename = path.to_str().unwrap();
+
+        // The BOM tells the byte order, kept to follow the file
+        let mut records = RecordList::new();
+        records.encoding = Encoding::Utf16Le;
+        let (tx, rx) = mpsc::channel();
+        records.readfile_parallel(filename, None, tx);
+        load_events(&mut records, &rx);
+        assert_eq!(records.encoding, Encoding::Utf16Be);
+        let lines: Vec<(&str, Option<usize>, Option<u64>)> = records
+            .visible_records
+            .iter()
+            .map(|r| (r.original.as_str(), r.line_number, r.byte_offset))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![("a", Some(1), Some(2)), ("\u{fffd}b", Some(2), Some(6))]
+        );
+
+        // Following from the start skips the BOM, and after invalid input stays aligned
+        let options = FollowOptions {
+            encoding: Encoding::Utf16Le,
+            ..FollowOptions::default()
+        };
+        let mut file = std::fs::File::open(&path).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let position =
+            RecordList::follow_file(filename, None, &tx, &mut file, FilePosition::START, options);
+        assert_eq!(received_lines(&rx), vec!["a", "\u{fffd}b"]);
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&be("c\n"))
+            .unwrap();
+        let position = RecordList::follow_file(filename, None, &tx, &mut file, position, options);
+        assert_eq!(received_lines(&rx), vec!["c"]);
+        assert_eq!(
+            position,
+            FilePosition {
+                offset: content.len() as u64 + 4,
+                line_number: Some(4)
+            }
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_readfile_tail_and_load_older() {
+        let path = std::env::temp_dir().join(format!("tailtales_tail_{}.log", std::process::id()));
+        let content: String = (1..=10).map(|i| format!("n={}\n", i)).collect();
+        std::fs::write(&path, &content).unwrap();
+        let filename = path.to_str().unwrap();
+
+        let mut file = std::fs::File::open(filename).unwrap();
+        let size = content.len() as u64;
+        assert_eq!(tail_start(&mut file, size, Tail::Lines(2)).unwrap(), 32);
+        assert_eq!(tail_start(&mut file, size, Tail::Lines(20)).unwrap(), 0);
+        // Inside "n=9\n", so from the next line; at a line start, from it
+        assert_eq!(tail_start(&mut file, size, Tail::Bytes(6)).unwrap(), 36);
+        assert_eq!(tail_start(&mut file, size, Tail::Bytes(9)).unwrap(), 32);
+        assert_eq!(tail_start(&mut file, size, Tail::Bytes(100)).unwrap(), 0);
+        assert_eq!(lines_start(&mut file, 20, 3).unwrap(), 8);
+
+        let mut records = RecordList::new();
+        records.parsers = vec![Parser::new("logfmt").unwrap()];
+        let (tx, rx) = mpsc::channel();
+        records.readfile_parallel(filename, Some(Tail::Lines(3)), tx);
+        load_events(&mut records, &rx);
+        let lines: Vec<&str> = records
+            .visible_records
+            .iter()
+            .map(|r| r.original.as_str())
+            .collect();
+        assert_eq!(lines, vec!["n=8", "n=9", "n=10"]);
+        assert_eq!(records.older.as_ref().unwrap().start, 28);
+        // The offset is known, but not the line number, as the start was not read
+        assert_eq!(records.all_records[0].byte_offset, Some(28));
+        assert_eq!(records.all_records[0].line_number, None);
+
+        // Older lines go before, with the filter
+        records.filter_parallel(crate::ast::parse("n > 4").unwrap());
+        assert_eq!(records.load_older(4), 3);
+        let lines: Vec<&str> = records
+            .visible_records
+            .iter()
+            .map(|r| r.original.as_str())
+            .collect();
+        assert_eq!(lines, vec!["n=5", "n=6", "n=7", "n=8", "n=9", "n=10"]);
+        assert_eq!(records.visible_records[0].index, 0);
+        assert_eq!(records.all_records.len(), 7);
+
+        // Older lines that do not pass the filter are still loaded
+        assert_eq!(records.load_older(100), 0);
+        assert_eq!(records.all_records.len(), 10);
+        assert_eq!(records.all_records[1].line_number, Some(2));
+        assert!(records.older.is_none());
+        assert_eq!(records.load_older(100), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_readfile_time_range() {
+        let path = std::env::temp_dir().join(format!("tailtales_time_{}.log", std::process::id()));
+        // A minute per line, and lines without timestamp continuing some of them
+        let content: String = (0..100)
+            .map(|i| match i % 10 {
+                5 => "  continuation\n".to_string(),
+                _ => format!("2025-03-01 10:{:02}:00 line {}\n", i / 2, i),
+            })
+            .collect();
+        std::fs::write(&path, &content).unwrap();
+        let filename = path.to_str().unwrap();
+        let time = |minute| Utc.with_ymd_and_hms(2025, 3, 1, 10, minute, 0).unwrap();
+
+        let mut records = RecordList::new();
+        records.parsers =
+            vec![Parser::new(r"regex (?P<timestamp>\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2})").unwrap()];
+        let (tx, rx) = mpsc::channel();
+        records.readfile_time_range(filename, Some(time(10)), Some(time(12)), tx);
+        load_events(&mut records, &rx);
+        let lines: Vec<&str> = records
+            .visible_records
+            .iter()
+            .map(|r| r.original.as_str())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "2025-03-01 10:10:00 line 20",
+                "2025-03-01 10:10:00 line 21",
+                "2025-03-01 10:11:00 line 22",
+                "2025-03-01 10:11:00 line 23",
+                "2025-03-01 10:12:00 line 24",
+                "  continuation",
+            ]
+        );
+        assert!(records.older.is_some());
+
+        // Out of the file
+        let file = std::fs::File::open(filename).unwrap();
+        let size = content.len() as u64;
+        let parsers = &records.parsers;
+        assert_eq!(
+            time_position(&file, size, parsers, |t| t >= time(0)).unwrap(),
+            0
+        );
+        assert_eq!(
+            time_position(&file, size, parsers, |t| t > time(59)).unwrap(),
+            size
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_readfiles_merged_window() {
+        let dir =
+            std::env::temp_dir().join(format!("tailtales_merge_window_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.log");
+        let b = dir.join("b.log");
+        let lines = |offset| -> String {
+            (0..10)
+                .map(|i| format!("2025-03-01 10:{:02}:00 line {}\n", i * 2 + offset, i))
+                .collect()
+        };
+        std::fs::write(&a, lines(0)).unwrap();
+        std::fs::write(&b, lines(1)).unwrap();
+        let time = |minute| Utc.with_ymd_and_hms(2025, 3, 1, 10, minute, 0).unwrap();
+        let read = |since, until, tail| -> Vec<(String, String)> {
+            let rule = || SourceRule {
+                parsers: vec![Parser::new(
+                    r"regex (?P<timestamp>\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2})",
+                )
+                .unwrap()],
+                ..SourceRule::default()
+            };
+            let mut records = RecordList::new();
+            let (tx, rx) = mpsc::channel();
+            records.readfiles_merged(
+                vec![
+                    (a.to_string_lossy().to_string(), rule()),
+                    (b.to_string_lossy().to_string(), rule()),
+                ],
+                since,
+                until,
+                tail,
+                tx,
+            );
+            load_events(&mut records, &rx);
+            load_events(&mut records, &rx);
+            records
+                .visible_records
+                .iter()
+                .map(|r| (r.get("source").unwrap().clone(), r.original.clone()))
+                .collect()
+        };
+        let expected = |lines: &[(&str, &str)]| -> Vec<(String, String)> {
+            lines
+                .iter()
+                .map(|(source, line)| (source.to_string(), line.to_string()))
+                .collect()
+        };
+
+        assert_eq!(
+            read(Some(time(4)), Some(time(7)), None),
+            expected(&[
+                ("a.log", "2025-03-01 10:04:00 line 2"),
+                ("b.log", "2025-03-01 10:05:00 line 2"),
+                ("a.log", "2025-03-01 10:06:00 line 3"),
+                ("b.log", "2025-03-01 10:07:00 line 3"),
+            ])
+        );
+        // The tail of each file
+        assert_eq!(
+            read(None, None, Some(Tail::Lines(2))),
+            expected(&[
+                ("a.log", "2025-03-01 10:16:00 line 8"),
+                ("b.log", "2025-03-01 10:17:00 line 8"),
+                ("a.log", "2025-03-01 10:18:00 line 9"),
+                ("b.log", "2025-03-01 10:19:00 line 9"),
+            ])
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn received_records(rx: &mpsc::Receiver<TuiEvent>, count: usize) -> Vec<Record> {
+        (0..count)
+            .map(|_| match rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+                TuiEvent::NewRecord(record) => record,
+                _ => panic!("Unexpected event"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_send_lines_positions() {
+        let (tx, rx) = mpsc::channel();
+        send_lines("one\r\ntwo\nthree".as_bytes(), None, Encoding::Utf8, &tx);
+        let records = received_records(&rx, 3);
+        let positions: Vec<(Option<usize>, Option<u64>)> = records
+            .iter()
+            .map(|record| (record.line_number, record.byte_offset))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![(Some(1), Some(0)), (Some(2), Some(5)), (Some(3), Some(9))]
+        );
+        assert_eq!(records[2].get("byte_offset").unwrap(), "9");
+    }
+
+    #[test]
+    fn test_listen_udp_and_tcp() {
+        use std::io::Write;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            receive_udp(
+                socket,
+                "udp://test",
+                Encoding::Utf8,
+                PeerSenders::new(None, tx),
+            )
+        });
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender
+            .send_to(b"<34>Oct 11 22:14:15 host su: failed\n", address)
+            .unwrap();
+        let records = received_records(&rx, 1);
+        assert_eq!(records[0].original, "<34>Oct 11 22:14:15 host su: failed");
+        assert_eq!(
+            records[0].get("source").unwrap(),
+            &sender.local_addr().unwrap().to_string()
+        );
+        assert_eq!(records[0].get("filename").unwrap(), "udp://test");
+
+        // Octet counted and newline delimited messages in the same stream
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut client = std::net::TcpStream::connect(address).unwrap();
+        let (stream, peer) = listener.accept().unwrap();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || receive_stream(stream, &peer.to_string(), "tcp://test", tx));
+        client
+            .write_all(b"9 <13>first2024-01-01 second\n9 <13>third")
+            .unwrap();
+        drop(client);
+        let records = received_records(&rx, 3);
+        let lines: Vec<&str> = records.iter().map(|r| r.original.as_str()).collect();
+        assert_eq!(lines, vec!["<13>first", "2024-01-01 second", "<13>third"]);
+        assert_eq!(records[0].get("source").unwrap(), &peer.to_string());
+    }
+
+    #[test]
+    fn test_listen_multiline_per_peer() {
+        use std::io::Write;
+
+        let mut records = RecordList::new();
+        records.multiline = Some(
+            MultilineMatcher::new(&crate::settings::MultilineSettings {
+                start: None,
+                continuation: Some(r"^\s".to_string()),
+                timeout_ms: 50,
+            })
+            .unwrap(),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        let (tx, rx) = mpsc::channel();
+        records.listen(&format!("tcp://{}", address), tx).unwrap();
+
+        let mut first = std::net::TcpStream::connect(address).unwrap();
+        let mut second = std::net::TcpStream::connect(address).unwrap();
+        first.write_all(b"first\n").unwrap();
+        thread::sleep(Duration::from_millis(10));
+        second.write_all(b"second\n").unwrap();
+        thread::sleep(Duration::from_millis(10));
+        first.write_all(b"  of first\n").unwrap();
+        drop((first, second));
+
+        let mut lines: Vec<String> = received_records(&rx, 2)
+            .into_iter()
+            .map(|record| record.original)
+            .collect();
+        lines.sort();
+        assert_eq!(lines, vec!["first\n  of first", "second"]);
+
+        // Also each datagram peer
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        drop(socket);
+        let (tx, rx) = mpsc::channel();
+        records.listen(&format!("udp://{}", address), tx).unwrap();
+        let first = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let second = UdpSocket::bind("127.0.0.1:0").unwrap();
+        first.send_to(b"first", address).unwrap();
+        thread::sleep(Duration::from_millis(10));
+        second.send_to(b"second", address).unwrap();
+        thread::sleep(Duration::from_millis(10));
+        first.send_to(b"  of first", address).unwrap();
+
+        let mut lines: Vec<String> = received_records(&rx, 2)
+            .into_iter()
+            .map(|record| record.original)
+            .collect();
+        lines.sort();
+        assert_eq!(lines, vec!["first\n  of first", "second"]);
+    }
+
+    #[test]
+    fn test_receive_stream_oversized_length() {
+        // Not allocated, read up to the newline
+        let (tx, rx) = mpsc::channel();
+        receive_stream(
+            "99999999999 <13>huge\n5 <13>a".as_bytes(),
+            "peer",
+            "tcp://test",
+            tx,
+        );
+        let records = received_records(&rx, 2);
+        assert_eq!(records[0].original, "99999999999 <13>huge");
+        assert_eq!(records[1].original, "<13>a");
+    }
+
+    #[test]
+    fn test_receive_stream_digits() {
+        // Newline delimited messages that start with digits are not joined
+        let (tx, rx) = mpsc::channel();
+        receive_stream(
+            "200\nnext 1\n12 <13>counted\n42 answers\n".as_bytes(),
+            "peer",
+            "tcp://test",
+            tx,
+        );
+        let lines: Vec<String> = received_records(&rx, 4)
+            .into_iter()
+            .map(|record| record.original)
+            .collect();
+        assert_eq!(lines, vec!["200", "next 1", "<13>counted", "42 answers"]);
+    }
+
+    #[test]
+    fn test_listen_unix() {
+        let path =
+            std::env::temp_dir().join(format!("tailtales_listen_{}.sock", std::process::id()));
+        let mut records = RecordList::new();
+        let (tx, rx) = mpsc::channel();
+        records
+            .listen(&format!("unixgram://{}", path.display()), tx)
+            .unwrap();
+
+        let sender = UnixDatagram::unbound().unwrap();
+        sender.send_to(b"hello\nworld", &path).unwrap();
+        let records = received_records(&rx, 2);
+        assert_eq!(records[0].original, "hello");
+        assert_eq!(records[1].original, "world");
+        assert_eq!(
+            records[0].get("source").unwrap(),
+            &format!("unixgram://{}", path.display())
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(RecordList::new()
+            .listen("localhost:5514", mpsc::channel().0)
+            .is_err());
+        assert!(RecordList::new()
+            .listen("http://localhost:5514", mpsc::channel().0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_follow_file_rotation_and_truncation() {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("tailtales_follow_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        let filename = path.to_string_lossy().to_string();
+        std::fs::write(&path, "first\n").unwrap();
+
+        let mut file = std::fs::File::open(&path).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let options = FollowOptions {
+            reload_on_truncate: false,
+            separator: true,
+            ..FollowOptions::default()
+        };
+
+        // Partial lines are left for later
+        let mut writer = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        write!(writer, "second\nthi").unwrap();
+        let position = FilePosition {
+            offset: 6,
+            line_number: Some(2),
+        };
+        let position = RecordList::follow_file(&filename, None, &tx, &mut file, position, options);
+        assert_eq!(received_lines(&rx), vec!["second"]);
+        assert_eq!(
+            position,
+            FilePosition {
+                offset: 13,
+                line_number: Some(3)
+            }
+        );
+
+        // Rotated: the end of the old file is read, then all the new one
+        write!(writer, "rd\n").unwrap();
+        drop(writer);
+        std::fs::rename(&path, dir.join("app.log.1")).unwrap();
+        std::fs::write(&path, "new first\n").unwrap();
+        let position = RecordList::follow_file(&filename, None, &tx, &mut file, position, options);
+        assert_eq!(
+            received_lines(&rx),
+            vec![
+                "third".to_string(),
+                format!("--- {} rotated ---", filename),
+                "new first".to_string()
+            ]
+        );
+        assert_eq!(
+            position,
+            FilePosition {
+                offset: 10,
+                line_number: Some(2)
+            }
+        );
+
+        // Truncated, with and without reload
+        std::fs::write(&path, "again\n").unwrap();
+        let position = RecordList::follow_file(&filename, None, &tx, &mut file, position, options);
+        assert_eq!(
+            received_lines(&rx),
+            vec![
+                format!("--- {} truncated ---", filename),
+                "again".to_string()
+            ]
+        );
+
+        std::fs::write(&path, "").unwrap();
+        let reload = FollowOptions {
+            reload_on_truncate: true,
+            separator: true,
+            ..FollowOptions::default()
+        };
+        RecordList::follow_file(&filename, None, &tx, &mut file, position, reload);
+        assert_eq!(received_lines(&rx), vec!["<clear>"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_clear_source() {
+        let mut records = RecordList::new();
+        for (source, line) in [("a", "1"), ("b", "2"), ("a", "3")] {
+            let mut record = Record::new(line.to_string());
+            record.set_data("source", source.to_string());
+            records.add_record(record, None);
+        }
+        records.clear_source(Some("a"));
+        assert_eq!(records.all_records.len(), 1);
+        assert_eq!(records.visible_records[0].original, "2");
+        assert_eq!(records.visible_records[0].index, 0);
+
+        records.clear_source(None);
+        assert_eq!(records.len(), 0);
+    }
+
+    #[test]
+    fn test_max_records() {
+        let mut records = RecordList::new();
+        records.max_records = Some(8);
+        for n in 1..=3 {
+            records.add_record(Record::new(n.to_string()), None);
+        }
+        assert_eq!(records.visible_records[2].index, 2);
+        records.get_mut(1).unwrap().set_data("mark", "red".into());
+        for n in 4..=12 {
+            records.add_record(Record::new(n.to_string()), None);
+        }
+
+        let originals: Vec<&str> = records
+            .visible_records
+            .iter()
+            .map(|record| record.original.as_str())
+            .collect();
+        assert_eq!(originals, vec!["2", "6", "7", "8", "9", "10", "11", "12"]);
+        assert_eq!(records.all_records.len(), 8);
+        assert_eq!(records.dropped, 4);
+        assert_eq!(records.dropped_visible, 4);
+        assert_eq!(records.visible_records[7].index, 7);
+
+        records.pin_marks = false;
+        records.add_record(Record::new("13".into()), None);
+        assert_eq!(records.get(0).unwrap().original, "6");
+        assert_eq!(records.dropped, 5);
+    }
+
+    #[test]
+    fn test_readfile_lazy() {
+        let path = std::env::temp_dir().join(format!("tailtales_lazy_{}.log", std::process::id()));
+        std::fs::write(&path, "level=info n=1\nlevel=error n=2\nlevel=info n=3\n").unwrap();
+
+        let mut records = RecordList::new();
+        records.parsers = vec![Parser::new("logfmt").unwrap()];
+        let (tx, _rx) = mpsc::channel();
+        records.readfile_lazy(path.to_str().unwrap(), tx);
+        assert!(records.all_records.is_empty());
+        assert_eq!(records.len(), 3);
+        assert!(records.get(1).is_none());
+
+        records.materialize(0..10);
+        assert_eq!(records.get(1).unwrap().get("level").unwrap(), "error");
+
+        records.get_mut(2).unwrap().set_data("mark", "red".into());
+        records.filter_parallel(crate::ast::parse("level == \"info\"").unwrap());
+        assert_eq!(records.len(), 2);
+        records.materialize(0..2);
+        assert_eq!(records.get(1).unwrap().get("mark").unwrap(), "red");
+        assert_eq!(
+            records.search_forward(&Filter::new(crate::ast::parse("n == 3").unwrap()), 0),
+            Some(1)
+        );
+
+        // New lines are parsed and filtered as they arrive, keeping their position
+        let position = FilePosition {
+            offset: 46,
+            line_number: Some(4),
+        };
+        let record = line_record(b"level=info n=4\n", "app.log", position);
+        records.add_record(record, None);
+        records.add_record(Record::new("level=error n=5".into()), None);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records.get(2).unwrap().get("line_number").unwrap(), "4");
+        assert_eq!(records.get(2).unwrap().byte_offset, Some(46));
+
+        records.clear_source(None);
+        assert!(records.lazy.is_none());
+        assert_eq!(records.len(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
     #[test]
     fn test_load_parsers_with_empty_file_scenario() {
         // Test the scenario with empty file (/dev/null) - default rule
@@ -520,10 +2877,13 @@ mod tests {
             ],
             filters: vec![],
             columns: vec![],
+            multiline: None,
+            max_records: None,
+            encoding: None,
         };
 
         let mut parsers = Vec::new();
-        let result = load_parsers(&rule, &mut parsers);
+        let result = load_parsers(&rule, &HashMap::new(), &mut parsers);
 
         // Should succeed without errors
         assert!(result.is_ok());
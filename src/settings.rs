@@ -1,8 +1,12 @@
 use ratatui::style::{Color, Style};
 use serde::{de::Deserializer, Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf, str::FromStr};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use crate::{ast, lua_engine::LuaEngine};
+use crate::{ast, filter::Filter, lua_engine::LuaEngine};
 
 // singleton load settings
 
@@ -18,6 +22,12 @@ pub struct Settings {
     pub keybindings: HashMap<String, String>,
     #[serde(default)]
     pub colors: GlobalColorSettings,
+    /// Named patterns for the grok extractor, shared by all the rules
+    #[serde(default)]
+    pub patterns: HashMap<String, String>,
+    /// Problems that did not stop the loading, as invalid saved filters
+    #[serde(skip)]
+    pub warnings: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -32,16 +42,83 @@ pub struct SettingsFromYaml {
     pub keybindings: Option<HashMap<String, String>>,
     #[serde(default)]
     pub colors: Option<GlobalColorSettings>,
+    #[serde(default)]
+    pub patterns: Option<HashMap<String, String>>,
+}
+
+/// Filters saved from the UI, by rule and name, as `rule: {name: expression}`.
+type SavedFilters = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Error of an invalid filter expression, naming the rule and the filter.
+fn invalid_filter(source: &str, rule: &str, filter: &str, error: ast::ParseError) -> String {
+    format!(
+        "Invalid expression {:?} in rule {}, filter {}: {}",
+        source, rule, filter, error
+    )
+}
+
+impl SettingsFromYaml {
+    /// Parses the filter expressions of the rules. Errors tell the rule and the filter.
+    fn parse_filters(&mut self) -> Result<(), String> {
+        for rule in &mut self.rules {
+            for (i, filter) in rule.filters.iter_mut().enumerate() {
+                let ast = ast::parse(&filter.source).map_err(|e| {
+                    let name = match &filter.name {
+                        Some(name) => name.clone(),
+                        None => format!("#{}", i + 1),
+                    };
+                    invalid_filter(&filter.source, &rule.name, &name, e)
+                })?;
+                filter.expression = Filter::new(ast);
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct GlobalSettings {
-    // pub reload_on_truncate: bool,
+    /// When a followed file is truncated, drop its records and read it again
+    #[serde(default)]
+    pub reload_on_truncate: bool,
+    /// Add a marked record where a followed file was rotated or truncated
+    #[serde(default = "default_rotation_separator")]
+    pub rotation_separator: bool,
+    /// Files bigger than this size (as `1GB`) are mapped and parsed lazily
+    #[serde(default = "default_lazy_threshold")]
+    pub lazy_threshold: Option<String>,
+    /// Maximum number of records kept, the oldest are dropped
+    #[serde(default)]
+    pub max_records: Option<usize>,
+    /// Marked records are kept when dropping the oldest ones
+    #[serde(default = "default_pin_marks")]
+    pub pin_marks: bool,
+    /// Read only the last lines of files, as with `-n`
+    #[serde(default)]
+    pub tail_lines: Option<usize>,
+    /// Read only the last bytes of files (as `50M`), as with `--bytes`
+    #[serde(default)]
+    pub tail_bytes: Option<String>,
+    /// How control characters and invalid UTF-8 bytes are shown
+    #[serde(default)]
+    pub binary_display: BinaryDisplay,
     pub gutter_symbol: String,
     #[serde(default)]
     pub symbols: SymbolSettings,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BinaryDisplay {
+    /// As read, with invalid bytes replaced by `�`
+    #[default]
+    Replace,
+    /// As `\x07`
+    Escape,
+    /// As `<07>`
+    Hex,
+}
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct SymbolSettings {
     #[serde(default = "default_tag_initial")]
@@ -54,6 +131,18 @@ pub struct SymbolSettings {
     pub tag_end: String,
 }
 
+fn default_rotation_separator() -> bool {
+    true
+}
+
+fn default_pin_marks() -> bool {
+    true
+}
+
+fn default_lazy_threshold() -> Option<String> {
+    Some("1GB".to_string())
+}
+
 fn default_tag_initial() -> String {
     "[".to_string()
 }
@@ -87,12 +176,33 @@ pub struct GlobalColorSettings {
     pub details: DetailsColorSettings,
     pub table: TableColorSettings,
     pub footer: FooterColorSettings,
+    /// Gutter colors for each input when several files are merged
+    #[serde(
+        deserialize_with = "parse_styles",
+        serialize_with = "serialize_styles",
+        default = "default_sources"
+    )]
+    pub sources: Vec<Style>,
 }
 
 fn default_highlight() -> Style {
     Style::new().fg(Color::White).bg(Color::Black)
 }
 
+fn default_sources() -> Vec<Style> {
+    [
+        Color::Cyan,
+        Color::Magenta,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Red,
+    ]
+    .into_iter()
+    .map(|color| Style::new().fg(color))
+    .collect()
+}
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct FooterColorSettings {
     #[serde(deserialize_with = "parse_style", serialize_with = "serialize_style")]
@@ -148,6 +258,67 @@ pub struct RulesSettings {
     pub filters: Vec<FilterSettings>,
     #[serde(default)]
     pub columns: Vec<ColumnSettings>,
+    #[serde(default)]
+    pub multiline: Option<MultilineSettings>,
+    /// Overrides the global `max_records` for this rule
+    #[serde(default)]
+    pub max_records: Option<usize>,
+    /// Encoding of the inputs, as `latin1` or `utf-16`. UTF-8 if not set.
+    #[serde(default)]
+    pub encoding: Option<String>,
+}
+
+/// How physical lines are joined into a single record, as for stack traces.
+///
+/// With `start`, lines that do not match it continue the previous record. With
+/// `continuation`, lines that match it continue the previous record.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct MultilineSettings {
+    #[serde(default)]
+    pub start: Option<String>,
+    #[serde(default)]
+    pub continuation: Option<String>,
+    /// On streamed input, the last record is shown after this time without new lines.
+    #[serde(default = "default_multiline_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_multiline_timeout_ms() -> u64 {
+    200
+}
+
+impl RulesSettings {
+    pub fn get_filter(&self, name: &str) -> Option<&FilterSettings> {
+        self.filters
+            .iter()
+            .find(|filter| filter.name.as_deref() == Some(name))
+    }
+
+    /// Adds a named filter, or changes the expression of the one with that name.
+    pub fn set_filter(&mut self, name: &str, source: &str) -> Result<(), String> {
+        let ast = ast::parse(source).map_err(|e| invalid_filter(source, &self.name, name, e))?;
+        let expression = Filter::new(ast);
+        match self
+            .filters
+            .iter_mut()
+            .find(|filter| filter.name.as_deref() == Some(name))
+        {
+            Some(filter) => {
+                filter.source = source.to_string();
+                filter.expression = expression;
+            }
+            None => self.filters.push(FilterSettings {
+                name: Some(name.to_string()),
+                source: source.to_string(),
+                expression,
+                highlight: None,
+                gutter: None,
+                gutter_symbol: String::new(),
+            }),
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -155,12 +326,11 @@ pub struct RulesSettings {
 pub struct FilterSettings {
     #[serde(default)]
     pub name: Option<String>,
-    #[serde(
-        default,
-        deserialize_with = "parse_expression",
-        serialize_with = "serialize_expression"
-    )]
-    pub expression: ast::AST,
+    /// The expression as written, parsed into `expression` when the settings are read
+    #[serde(rename = "expression", default)]
+    pub source: String,
+    #[serde(skip)]
+    pub expression: Filter,
     #[serde(
         default,
         deserialize_with = "parse_optional_style",
@@ -177,21 +347,6 @@ pub struct FilterSettings {
     pub gutter_symbol: String,
 }
 
-fn parse_expression<'de, D>(deserializer: D) -> Result<ast::AST, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s: String = Deserialize::deserialize(deserializer)?;
-    ast::AST::from_str(&s).map_err(serde::de::Error::custom)
-}
-
-fn serialize_expression<S>(expression: &ast::AST, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: serde::Serializer,
-{
-    serializer.serialize_str(&expression.to_string())
-}
-
 fn parse_style<'de, D>(deserializer: D) -> Result<Style, D::Error>
 where
     D: Deserializer<'de>,
@@ -218,10 +373,7 @@ pub fn string_to_style(s: &str) -> Result<Style, String> {
     Ok(style)
 }
 
-fn serialize_style<S>(style: &Style, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: serde::Serializer,
-{
+fn style_to_string(style: &Style) -> String {
     let mut s = String::new();
     if let Some(fg) = style.fg {
         s.push_str(&fg.to_string());
@@ -230,7 +382,32 @@ where
             s.push_str(&bg.to_string());
         }
     }
-    serializer.serialize_str(&s)
+    s
+}
+
+fn serialize_style<S>(style: &Style, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&style_to_string(style))
+}
+
+fn parse_styles<'de, D>(deserializer: D) -> Result<Vec<Style>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let styles: Vec<String> = Deserialize::deserialize(deserializer)?;
+    styles
+        .iter()
+        .map(|s| string_to_style(s).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+fn serialize_styles<S>(styles: &[Style], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_seq(styles.iter().map(style_to_string))
 }
 
 fn parse_optional_style<'de, D>(deserializer: D) -> Result<Option<Style>, D::Error>
@@ -320,6 +497,10 @@ impl Settings {
             }
         }
 
+        if let Some(filename) = Self::saved_filters_filename() {
+            settings.warnings = settings.read_saved_filters(&filename);
+        }
+
         Ok(settings)
     }
     pub fn default_settings_yaml_data() -> &'static str {
@@ -336,6 +517,64 @@ impl Settings {
         xdg.unwrap().find_config_file("settings.yaml")
     }
 
+    pub fn saved_filters_filename() -> Option<PathBuf> {
+        let xdg = xdg::BaseDirectories::with_prefix("tailtales").ok()?;
+        xdg.place_config_file("filters.yaml").ok()
+    }
+
+    /// Adds the saved filters to their rules. The invalid ones are skipped, and returned
+    /// as warnings with the problems reading the file.
+    pub fn read_saved_filters(&mut self, filename: &Path) -> Vec<String> {
+        if !filename.exists() {
+            return vec![];
+        }
+        let saved: Result<SavedFilters, Box<dyn std::error::Error>> =
+            std::fs::read_to_string(filename)
+                .map_err(|e| e.into())
+                .and_then(|contents| serde_yaml::from_str(&contents).map_err(|e| e.into()));
+        let saved = match saved {
+            Ok(saved) => saved,
+            Err(e) => {
+                return vec![format!(
+                    "Error reading saved filters from {}: {}",
+                    filename.display(),
+                    e
+                )]
+            }
+        };
+        let mut warnings = vec![];
+        for (rule_name, filters) in saved {
+            if let Some(rule) = self.rules.iter_mut().find(|rule| rule.name == rule_name) {
+                for (name, source) in filters {
+                    if let Err(warning) = rule.set_filter(&name, &source) {
+                        warnings.push(warning);
+                    }
+                }
+            }
+        }
+        warnings
+    }
+
+    /// Adds a filter to the saved filters file, or changes it.
+    pub fn save_filter(
+        filename: &Path,
+        rule_name: &str,
+        name: &str,
+        source: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut saved: SavedFilters = if filename.exists() {
+            serde_yaml::from_str(&std::fs::read_to_string(filename)?)?
+        } else {
+            SavedFilters::new()
+        };
+        saved
+            .entry(rule_name.to_string())
+            .or_default()
+            .insert(name.to_string(), source.to_string());
+        std::fs::write(filename, serde_yaml::to_string(&saved)?)?;
+        Ok(())
+    }
+
     #[allow(dead_code)]
     pub fn save_default_settings(&self) -> Result<(), Box<dyn std::error::Error>> {
         let xdg = xdg::BaseDirectories::with_prefix("tailtales")?;
@@ -349,7 +588,8 @@ impl Settings {
     pub fn read_from_yaml(&mut self, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
         let file = std::fs::File::open(filename)?;
         let reader = std::io::BufReader::new(file);
-        let settings: SettingsFromYaml = serde_yaml::from_reader(reader)?;
+        let mut settings: SettingsFromYaml = serde_yaml::from_reader(reader)?;
+        settings.parse_filters()?;
 
         self.merge_with(settings);
 
@@ -357,7 +597,8 @@ impl Settings {
     }
 
     pub fn read_from_string(&mut self, s: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let settings: SettingsFromYaml = serde_yaml::from_str(s)?;
+        let mut settings: SettingsFromYaml = serde_yaml::from_str(s)?;
+        settings.parse_filters()?;
         self.merge_with(settings);
 
         Ok(())
@@ -379,6 +620,10 @@ impl Settings {
             self.keybindings.extend(other.keybindings.unwrap());
         }
 
+        if let Some(patterns) = other.patterns {
+            self.patterns.extend(patterns);
+        }
+
         if other.colors.is_some() {
             let other_colors = other.colors.unwrap();
             self.colors.normal = other_colors.normal;
@@ -388,6 +633,7 @@ impl Settings {
             self.colors.details = other_colors.details;
             self.colors.table = other_colors.table;
             self.colors.footer = other_colors.footer;
+            self.colors.sources = other_colors.sources;
         }
 
         self.rules = other_rules
@@ -479,4 +725,62 @@ mod tests {
         settings.read_from_yaml("settings.yaml").unwrap();
         println!("{:#?}", settings);
     }
+
+    #[test]
+    fn test_saved_filters() {
+        let filename =
+            std::env::temp_dir().join(format!("tailtales_filters_{}.yaml", std::process::id()));
+        Settings::save_filter(&filename, "web", "slow", "took > 1s").unwrap();
+        Settings::save_filter(&filename, "web", "errors", "status >= 400").unwrap();
+        Settings::save_filter(&filename, "other", "all", "").unwrap();
+        Settings::save_filter(&filename, "web", "bad", "status >=").unwrap();
+
+        let mut settings = Settings::default();
+        let yaml = "rules:\n  - name: web\n    filters:\n      - name: errors\n        expression: status >= 500\n        highlight: red\n";
+        settings.read_from_string(yaml).unwrap();
+        // The invalid ones are skipped
+        assert_eq!(
+            settings.read_saved_filters(&filename),
+            vec![
+                "Invalid expression \"status >=\" in rule web, filter bad: \
+                  unexpected end of expression at column 10"
+            ]
+        );
+        std::fs::write(&filename, "web: [not, a, map]").unwrap();
+        let warnings = settings.read_saved_filters(&filename);
+        assert!(warnings[0].starts_with("Error reading saved filters from"));
+        std::fs::remove_file(&filename).unwrap();
+
+        let rule = &settings.rules[0];
+        assert_eq!(rule.filters.len(), 2);
+        // Same name, the expression changes and the style is kept
+        let errors = rule.get_filter("errors").unwrap();
+        assert_eq!(errors.source, "status >= 400");
+        assert!(errors.highlight.is_some());
+        assert_eq!(rule.get_filter("slow").unwrap().source, "took > 1s");
+        assert!(rule.get_filter("all").is_none());
+
+        let mut rule = rule.clone();
+        assert!(rule.set_filter("bad", "status >=").is_err());
+        assert!(rule.get_filter("bad").is_none());
+    }
+
+    #[test]
+    fn test_invalid_filter_expression() {
+        let mut settings = Settings::default();
+        let yaml = "rules:\n  - name: web\n    filters:\n      - name: errors\n        expression: status >= 500\n      - expression: status >=\n";
+        let error = settings.read_from_string(yaml).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Invalid expression \"status >=\" in rule web, filter #2: \
+             unexpected end of expression at column 10"
+        );
+
+        let yaml = "rules:\n  - name: web\n    filters:\n      - name: errors\n        expression: status >= 500\n";
+        settings.read_from_string(yaml).unwrap();
+        assert_eq!(
+            settings.rules[0].filters[0].expression,
+            Filter::new(ast::parse("status >= 500").unwrap())
+        );
+    }
 }
@@ -2,11 +2,17 @@ use std::time;
 
 use crate::{
     ast,
+    events::LoadProgress,
+    filter::Filter,
+    history::History,
     lua_console::LuaConsole,
-    recordlist::{self, load_parsers},
-    settings::{RulesSettings, Settings},
+    recordlist::{self, load_encoding, load_multiline, load_parsers},
+    settings::{BinaryDisplay, RulesSettings, Settings},
 };
 
+/// Lines of a file tail loaded each time the selection goes past the top
+const OLDER_PAGE_LINES: usize = 1000;
+
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Mode {
     Normal,
@@ -29,15 +35,25 @@ pub struct TuiState {
     pub scroll_offset_left: usize,
     pub running: bool,
     pub read_time: time::Duration,
+    /// Files being loaded in the background, several when they are merged
+    pub loading: Vec<LoadProgress>,
     pub mode: Mode,
     pub next_mode: Mode,
     pub search: String,
-    pub search_ast: Option<ast::AST>,
+    pub search_filter: Option<Filter>,
     pub filter: String,
-    pub filter_ok: bool,
+    /// Why the filter being written does not parse, if it does not
+    pub filter_error: Option<ast::ParseError>,
+    pub search_history: History,
+    pub filter_history: History,
     pub command: String,
     pub warning: String,
     pub view_details: bool,
+    /// Whether the selection moves with new records at the tail
+    pub following: bool,
+    /// Records added while not following
+    pub new_while_paused: usize,
+    pub binary_display: BinaryDisplay,
     pub text_edit_position: usize,
     pub pending_refresh: bool, // If true, the screen will be refreshed when the screen receives render request
     pub script_prompt: String,
@@ -54,11 +70,17 @@ impl TuiState {
         let current_rule = RulesSettings::default();
         let mut records = recordlist::RecordList::new();
 
-        if let Err(err) = load_parsers(&current_rule, &mut records.parsers) {
+        records.follow = recordlist::FollowOptions::new(&settings.global);
+        records.max_records = settings.global.max_records;
+        records.pin_marks = settings.global.pin_marks;
+        let binary_display = settings.global.binary_display;
+
+        if let Err(err) = load_parsers(&current_rule, &settings.patterns, &mut records.parsers) {
             return Err(format!("Could not load parsers: {:?}", err).into());
         }
 
-        Ok(TuiState {
+        let warnings = settings.warnings.join("; ");
+        let mut state = TuiState {
             settings,
             current_rule,
             records,
@@ -69,15 +91,21 @@ impl TuiState {
             scroll_offset_left: 0,
             running: true,
             read_time: time::Duration::new(0, 0),
+            loading: Vec::new(),
             mode: Mode::Normal,
             next_mode: Mode::Normal,
             search: String::new(),
-            search_ast: None,
+            search_filter: None,
             filter: String::new(),
-            filter_ok: true,
+            filter_error: None,
+            search_history: History::default(),
+            filter_history: History::default(),
             command: String::new(),
             warning: String::new(),
             view_details: false, // Default view_details value
+            following: true,
+            new_while_paused: 0,
+            binary_display,
             text_edit_position: 0,
             pending_refresh: false,
             script_prompt: String::new(),
@@ -85,7 +113,11 @@ impl TuiState {
             script_waiting: false,
             // Initialize Lua console
             lua_console: LuaConsole::new(),
-        })
+        };
+        if !warnings.is_empty() {
+            state.set_warning(warnings);
+        }
+        Ok(state)
     }
 
     pub fn search_next(&mut self) {
@@ -98,14 +130,14 @@ impl TuiState {
     }
 
     pub fn search_fwd(&mut self) -> bool {
-        let search_ast = self.search_ast.as_ref();
-        if search_ast.is_none() {
+        let search_filter = self.search_filter.as_ref();
+        if search_filter.is_none() {
             return false;
         }
-        let search_ast = search_ast.unwrap();
+        let search_filter = search_filter.unwrap();
         let mut current = self.position - 1; // Convert to 0-based for search
 
-        let maybe_position = self.records.search_forward(search_ast, current);
+        let maybe_position = self.records.search_forward(search_filter, current);
         if maybe_position.is_none() {
             return false;
         }
@@ -123,14 +155,14 @@ impl TuiState {
     }
 
     pub fn search_bwd(&mut self) -> bool {
-        let search_ast = self.search_ast.as_ref();
-        if search_ast.is_none() {
+        let search_filter = self.search_filter.as_ref();
+        if search_filter.is_none() {
             return false;
         }
-        let search_ast = search_ast.unwrap();
+        let search_filter = search_filter.unwrap();
         let mut current = self.position - 1; // Convert to 0-based for search
 
-        let maybe_position = self.records.search_backwards(search_ast, current);
+        let maybe_position = self.records.search_backwards(search_filter, current);
         if maybe_position.is_none() {
             return false;
         }
@@ -145,11 +177,104 @@ impl TuiState {
             Ok(parsed) => {
                 self.records.filter_parallel(parsed);
                 self.set_position(1); // Use 1-based indexing
-                self.filter_ok = true;
+                self.filter_error = None;
+            }
+            Err(err) => {
+                self.filter_error = Some(err);
+            }
+        }
+    }
+
+    /// Shows the previous search of the history, and looks for it.
+    pub fn search_history_up(&mut self) {
+        if self.search_history.up(&mut self.search) {
+            self.search_recalled();
+        }
+    }
+
+    /// Shows the next search of the history, and looks for it.
+    pub fn search_history_down(&mut self) {
+        if self.search_history.down(&mut self.search) {
+            self.search_recalled();
+        }
+    }
+
+    fn search_recalled(&mut self) {
+        self.text_edit_position = self.search.len();
+        self.search_filter = ast::parse(&self.search).ok().map(Filter::new);
+        self.search_fwd();
+    }
+
+    /// Shows the previous filter of the history, and applies it.
+    pub fn filter_history_up(&mut self) {
+        if self.filter_history.up(&mut self.filter) {
+            self.text_edit_position = self.filter.len();
+            self.handle_filter();
+        }
+    }
+
+    /// Shows the next filter of the history, and applies it.
+    pub fn filter_history_down(&mut self) {
+        if self.filter_history.down(&mut self.filter) {
+            self.text_edit_position = self.filter.len();
+            self.handle_filter();
+        }
+    }
+
+    /// Saves the current filter with a name in the current rule, and in the saved
+    /// filters file, to recall it later with `load_filter`.
+    pub fn save_filter(&mut self, name: &str) {
+        let name = name.trim();
+        if name.is_empty() || self.filter.trim().is_empty() {
+            self.set_warning("Nothing to save, a name and a filter are needed".to_string());
+            return;
+        }
+        if let Err(err) = self.current_rule.set_filter(name, &self.filter) {
+            self.set_warning(err);
+            return;
+        }
+        if let Some(rule) = self
+            .settings
+            .rules
+            .iter_mut()
+            .find(|rule| rule.name == self.current_rule.name)
+        {
+            *rule = self.current_rule.clone();
+        }
+        let saved = match Settings::saved_filters_filename() {
+            Some(filename) => {
+                Settings::save_filter(&filename, &self.current_rule.name, name, &self.filter)
+            }
+            None => Err("no config dir".into()),
+        };
+        match saved {
+            Ok(()) => self.set_warning(format!("Filter saved as {}", name)),
+            Err(err) => self.set_warning(format!("Could not save filter {}: {}", name, err)),
+        }
+    }
+
+    /// Applies the filter of the current rule with this name.
+    pub fn load_filter(&mut self, name: &str) {
+        let name = name.trim();
+        match self.current_rule.get_filter(name) {
+            Some(filter) => {
+                self.filter = filter.source.clone();
+                self.text_edit_position = self.filter.len();
+                self.handle_filter();
             }
-            Err(_err) => {
-                self.filter_ok = false;
-                // panic!("TODO show error parsing: {}", err);
+            None => {
+                let names: Vec<&str> = self
+                    .current_rule
+                    .filters
+                    .iter()
+                    .filter_map(|filter| filter.name.as_deref())
+                    .collect();
+                self.set_warning(format!(
+                    "Unknown filter {}. Filters of rule {}: {}",
+                    name,
+                    self.current_rule.name,
+                    names.join(", ")
+                ));
             }
         }
     }
@@ -166,9 +291,11 @@ impl TuiState {
             }
             "search" => {
                 self.mode = Mode::Search;
+                self.search_history = History::load("search_history");
             }
             "filter" => {
                 self.mode = Mode::Filter;
+                self.filter_history = History::load("filter_history");
             }
             "command" => {
                 self.mode = Mode::Command;
@@ -256,10 +383,20 @@ impl TuiState {
         None
     }
 
+    /// Cycles how control characters and invalid bytes are shown.
+    pub fn toggle_binary_display(&mut self) {
+        self.binary_display = match self.binary_display {
+            BinaryDisplay::Replace => BinaryDisplay::Escape,
+            BinaryDisplay::Escape => BinaryDisplay::Hex,
+            BinaryDisplay::Hex => BinaryDisplay::Replace,
+        };
+        self.set_warning(format!("Non-printable bytes: {:?}", self.binary_display));
+    }
+
     pub fn toggle_mark(&mut self, color: &str) {
         let color = color.to_string();
         let current = self.position - 1; // Convert to 0-based for array access
-        let record = self.records.visible_records.get_mut(current).unwrap();
+        let record = self.records.get_mut(current).unwrap();
         let current_value = record.get("mark");
         if current_value.is_some() {
             if *current_value.unwrap() == color {
@@ -273,11 +410,53 @@ impl TuiState {
         self.set_position_wrap(self.position as i32 + 1);
     }
 
+    pub fn set_load_progress(&mut self, progress: LoadProgress) {
+        self.loading
+            .retain(|loading| loading.filename != progress.filename);
+        if progress.done {
+            self.read_time = progress.elapsed;
+        } else {
+            self.loading.push(progress);
+        }
+    }
+
+    /// Keeps the position and scroll on the same records after the oldest are dropped.
+    pub fn records_dropped(&mut self, count: usize) {
+        self.position = self.position.saturating_sub(count).max(1);
+        self.scroll_offset_top = self.scroll_offset_top.saturating_sub(count);
+    }
+
+    /// New records were added at the end. When following and the selection is at the
+    /// tail, it moves with them. When paused, they are only counted.
+    pub fn records_added(&mut self, count: usize) {
+        if !self.following {
+            self.new_while_paused += count;
+        } else if count > 0 && self.position + count + 1 >= self.records.len() {
+            self.move_selection(count as i32);
+        }
+    }
+
+    /// Pauses or resumes following. Resuming jumps to the tail.
+    pub fn set_following(&mut self, following: bool) {
+        if following && !self.following {
+            self.set_position(0);
+        }
+        self.following = following;
+        self.new_while_paused = 0;
+    }
+
     pub fn move_selection(&mut self, delta: i32) {
         // I use i32 all around here as I may get some negatives
         let current = self.position as i32; // position is now 1-based
-        let new = current + delta;
-        let max = self.records.visible_records.len() as i32;
+        let mut new = current + delta;
+        if new < 1 && self.records.older.is_some() {
+            // Past the top of a file tail, the older lines are loaded before
+            let added = self.records.load_older(OLDER_PAGE_LINES);
+            self.position += added;
+            self.scroll_offset_top += added;
+            new += added as i32;
+        }
+        let max = self.records.len() as i32;
 
         if new < 1 {
             self.set_position(1); // Use 1-based indexing
@@ -309,7 +488,7 @@ impl TuiState {
     }
 
     pub fn set_position(&mut self, position: usize) {
-        let visible_len = self.records.visible_records.len();
+        let visible_len = self.records.len();
         if visible_len == 0 {
             self.position = 1; // Use 1-based indexing
         } else if position > visible_len {
@@ -325,7 +504,7 @@ impl TuiState {
     }
 
     pub fn set_position_wrap(&mut self, position: i32) {
-        let max = self.records.visible_records.len() as i32;
+        let max = self.records.len() as i32;
         if max <= 0 {
             self.position = 1 // Use 1-based indexing
         } else if position > max {
@@ -398,16 +577,24 @@ impl TuiState {
     }
     pub fn move_to_next_mark(&mut self) {
         let current = self.position;
-        let max = self.records.visible_records.len();
+        let max = self.records.len();
 
         for new in current + 1..max {
-            if self.records.visible_records[new].get("mark").is_some() {
+            if self
+                .records
+                .get(new)
+                .is_some_and(|record| record.get("mark").is_some())
+            {
                 self.set_position(new);
                 return;
             }
         }
         for new in 0..current {
-            if self.records.visible_records[new].get("mark").is_some() {
+            if self
+                .records
+                .get(new)
+                .is_some_and(|record| record.get("mark").is_some())
+            {
                 self.set_position(new);
                 return;
             }
@@ -417,16 +604,24 @@ impl TuiState {
 
     pub fn move_to_prev_mark(&mut self) {
         let current = self.position;
-        let max = self.records.visible_records.len();
+        let max = self.records.len();
 
         for new in (0..current).rev() {
-            if self.records.visible_records[new].get("mark").is_some() {
+            if self
+                .records
+                .get(new)
+                .is_some_and(|record| record.get("mark").is_some())
+            {
                 self.set_position(new);
                 return;
             }
         }
         for new in (current + 1..max).rev() {
-            if self.records.visible_records[new].get("mark").is_some() {
+            if self
+                .records
+                .get(new)
+                .is_some_and(|record| record.get("mark").is_some())
+            {
                 self.set_position(new);
                 return;
             }
@@ -462,6 +657,9 @@ impl TuiState {
     pub fn reload_settings(&mut self) {
         let filename = Settings::local_settings_filename().unwrap();
         let result = self.settings.read_from_yaml(filename.to_str().unwrap());
+        if let (Ok(_), Some(saved_filters)) = (&result, Settings::saved_filters_filename()) {
+            self.settings.warnings = self.settings.read_saved_filters(&saved_filters);
+        }
         match result {
             Ok(_) => {
                 // Note: Keybinding scripts compilation now needs to be done from Application
@@ -473,14 +671,34 @@ impl TuiState {
                     .find(|r| r.name == self.current_rule.name)
                     .unwrap()
                     .clone();
-                match load_parsers(&self.current_rule, &mut self.records.parsers) {
+                match load_parsers(
+                    &self.current_rule,
+                    &self.settings.patterns,
+                    &mut self.records.parsers,
+                ) {
                     Ok(_) => {}
                     Err(err) => {
                         self.set_warning(format!("Error loading parsers: {:?}", err));
                     }
                 }
+                match load_multiline(&self.current_rule) {
+                    Ok(multiline) => self.records.multiline = multiline,
+                    Err(err) => {
+                        self.set_warning(format!("Error loading multiline: {:?}", err));
+                    }
+                }
+                match load_encoding(&self.current_rule) {
+                    Ok(encoding) => self.records.encoding = encoding,
+                    Err(err) => {
+                        self.set_warning(format!("Error loading encoding: {:?}", err));
+                    }
+                }
                 self.records.reparse();
-                self.set_warning("Settings reloaded".into());
+                if self.settings.warnings.is_empty() {
+                    self.set_warning("Settings reloaded".into());
+                } else {
+                    self.set_warning(self.settings.warnings.join("; "));
+                }
                 self.refresh_screen();
             }
             Err(err) => {
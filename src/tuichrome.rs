@@ -1,15 +1,19 @@
+use crate::ast;
 use crate::events::TuiEvent;
 use crate::lua_console::{render_console_footer, render_console_output};
 use crate::record;
-use crate::settings::string_to_style;
+use crate::settings::{string_to_style, BinaryDisplay};
 use crate::state::{Mode, TuiState};
-use crate::utils::{ansi_to_style, clean_ansi_text, parse_tabs, reverse_style};
+use crate::utils::{
+    ansi_to_style, clean_ansi_text, escape_nonprintable, format_bytes, parse_tabs, reverse_style,
+};
 
 use crossterm::ExecutableCommand;
 use ratatui::{prelude::*, widgets::*};
 use std::cmp::max;
 use std::cmp::min;
 use std::io;
+use std::path::Path;
 use std::sync::mpsc;
 
 pub struct TuiChrome {
@@ -40,9 +44,16 @@ impl TuiChrome {
             state.visible_width = visible_width as usize;
         }
 
+        // Lazy files only parse the records when they are shown
+        if state.position > 0 {
+            state
+                .records
+                .materialize(state.position - 1..state.position);
+        }
+
         let mut visible_lines = self.terminal.size()?.height as i32 - 2; // header and footer
-        if state.view_details && state.records.visible_records.len() > 0 {
-            if let Some(record) = state.records.visible_records.get(state.position - 1) {
+        if state.view_details && state.records.len() > 0 {
+            if let Some(record) = state.records.get(state.position - 1) {
                 visible_lines = visible_lines - 3 - 2; // frame + separator + padding
                 visible_lines = visible_lines - record.data.len() as i32; // data lines
 
@@ -64,6 +75,11 @@ impl TuiChrome {
             state.pending_refresh = false;
         }
 
+        let start = state.scroll_offset_top;
+        state
+            .records
+            .materialize(start..start + state.visible_height);
+
         Ok(())
     }
 
@@ -154,7 +170,7 @@ impl TuiChrome {
         state: &'a TuiState,
     ) -> Option<&'a crate::record::Record> {
         if state.view_details {
-            state.records.visible_records.get(state.position - 1)
+            state.records.get(state.position - 1)
         } else {
             None
         }
@@ -188,14 +204,10 @@ impl TuiChrome {
         let current_rules = &state.current_rule;
         let columns = &current_rules.columns;
         let start = state.scroll_offset_top;
-        let end = min(
-            start + state.visible_height,
-            state.records.visible_records.len(),
-        );
+        let end = min(start + state.visible_height, state.records.len());
 
-        let records = &state.records.visible_records;
         let mut rows = Vec::new();
-        for record in records[start..end].iter() {
+        for record in (start..end).filter_map(|i| state.records.get(i)) {
             let mut cells: Vec<Cell> = columns
                 .iter()
                 .map(|column| {
@@ -325,8 +337,14 @@ impl TuiChrome {
     }
 
     fn render_record_original<'a>(state: &'a TuiState, record: &record::Record) -> Line<'a> {
-        let original = &record.original;
-        let original = parse_tabs(original);
+        // Multiline records use a single row, with each line starting at the next tab stop.
+        // The details view shows them line by line.
+        let original = match state.binary_display {
+            BinaryDisplay::Replace => record.original.clone(),
+            BinaryDisplay::Escape => escape_nonprintable(record.original_bytes(), false),
+            BinaryDisplay::Hex => escape_nonprintable(record.original_bytes(), true),
+        };
+        let original = parse_tabs(&original.replace('\n', "\t"));
         let voffset = state.scroll_offset_left;
         let initial_style = Self::get_row_style(state, &record);
 
@@ -373,7 +391,7 @@ impl TuiChrome {
         let filters = &state.current_rule.filters;
 
         for filter in filters {
-            if record.matches(&filter.expression) {
+            if filter.expression.matches(record) {
                 if filter.gutter.is_some() {
                     return Span::styled(
                         filter.gutter_symbol.clone(),
@@ -383,6 +401,21 @@ impl TuiChrome {
             }
         }
 
+        // When several files are merged, the color tells where the line came from
+        let sources = &state.records.sources;
+        let colors = &state.settings.colors.sources;
+        if sources.len() > 1 && !colors.is_empty() {
+            if let Some(i) = record
+                .get("source")
+                .and_then(|source| sources.iter().position(|s| s == source))
+            {
+                return Span::styled(
+                    state.settings.global.gutter_symbol.clone(),
+                    colors[i % colors.len()],
+                );
+            }
+        }
+
         return Span::styled(" ", Style::from(state.settings.colors.normal));
     }
 
@@ -410,7 +443,7 @@ impl TuiChrome {
         }
 
         for filter in &filters.filters {
-            if record.matches(&filter.expression) {
+            if filter.expression.matches(record) {
                 if filter.highlight.is_some() {
                     return Style::from(filter.highlight.unwrap());
                 }
@@ -454,11 +487,18 @@ impl TuiChrome {
         lines
     }
 
+    // Wraps each line of the record, as multiline records keep their line breaks
+    fn wrap_record_original(record: &record::Record, width: usize) -> Vec<String> {
+        record
+            .original
+            .lines()
+            .flat_map(|line| Self::wrap_text(&clean_ansi_text(line), width))
+            .collect()
+    }
+
     fn record_wrap_lines_count(record: &record::Record, state: &TuiState) -> usize {
         let title_width = state.visible_width - 2; // Account for borders
-        let title_text = clean_ansi_text(&record.original);
-        let wrapped_title = Self::wrap_text(&title_text, title_width);
-        wrapped_title.len()
+        Self::wrap_record_original(record, title_width).len()
     }
 
     pub fn render_record_details<'a>(
@@ -470,8 +510,7 @@ impl TuiChrome {
 
         // Get the available width for the title (accounting for borders)
         let title_width = state.visible_width - 2; // Account for borders
-        let title_text = clean_ansi_text(&record.original);
-        let wrapped_title = Self::wrap_text(&title_text, title_width);
+        let wrapped_title = Self::wrap_record_original(record, title_width);
 
         // Add all wrapped lines at the beginning
         for line in &wrapped_title {
@@ -536,18 +575,22 @@ impl TuiChrome {
         )
     }
     pub fn render_footer_filter(state: &TuiState) -> Block {
-        let style = if state.filter_ok {
-            state.settings.colors.footer.filter
-        } else {
-            Style::default().fg(Color::Red).bg(Color::Black)
-        };
-        Self::render_textinput_block(
-            "Filter",
-            &state.filter,
-            state.text_edit_position,
-            style,
-            &state.settings.global.symbols,
-        )
+        match &state.filter_error {
+            None => Self::render_textinput_block(
+                "Filter",
+                &state.filter,
+                state.text_edit_position,
+                state.settings.colors.footer.filter,
+                &state.settings.global.symbols,
+            ),
+            Some(error) => Self::render_textinput_error_block(
+                "Filter",
+                &state.filter,
+                state.text_edit_position,
+                error,
+                &state.settings.global.symbols,
+            ),
+        }
     }
     pub fn render_footer_command(state: &TuiState) -> Block {
         Self::render_textinput_block(
@@ -638,6 +681,45 @@ impl TuiChrome {
         Block::default().title(line)
     }
 
+    /// Like `render_textinput_block`, but with the text of the error underlined and
+    /// the message after it.
+    pub fn render_textinput_error_block<'a>(
+        label: &'a str,
+        value: &'a str,
+        position: usize,
+        error: &ast::ParseError,
+        symbols: &crate::settings::SymbolSettings,
+    ) -> Block<'a> {
+        let style = Style::default().fg(Color::Red).bg(Color::Black);
+        let rstyle = reverse_style(style);
+        let mut spans = vec![
+            Span::styled(
+                format!("{}{}{}", symbols.tag_initial, label, symbols.tag_mid_left),
+                rstyle,
+            ),
+            Span::styled(symbols.tag_mid_right.clone(), style),
+        ];
+
+        // An error at the end, as a missing ')', is shown after the text
+        let chars = value
+            .char_indices()
+            .chain(std::iter::once((value.len(), ' ')));
+        for (i, (offset, c)) in chars.enumerate() {
+            let mut char_style = if i == position { rstyle } else { style };
+            if error.span.contains(&offset) || (error.span.is_empty() && offset == error.span.start)
+            {
+                char_style = char_style.add_modifier(Modifier::UNDERLINED);
+            }
+            spans.push(Span::styled(c.to_string(), char_style));
+        }
+        spans.push(Span::styled(
+            format!("{} {}", symbols.tag_end, error.message),
+            style,
+        ));
+
+        Block::default().title(Line::from(spans))
+    }
+
     pub fn render_footer_normal(state: &TuiState) -> Block {
         // let filter_ast = state.search_ast.as_ref().unwrap_or(&ast::AST::Empty);
 
@@ -695,12 +777,69 @@ impl TuiChrome {
             format!(
                 " {:5} / {:5} ",
                 state.position, // Already 1-based internally
-                state.records.visible_records.len()
+                state.records.len()
             )
             .as_str(),
             state.settings.colors.footer.line_number,
             &state.settings.global.symbols,
         );
+        if !state.loading.is_empty() {
+            let loading = &state.loading;
+            let name = match loading.as_slice() {
+                [progress] => Path::new(&progress.filename)
+                    .file_name()
+                    .map_or(progress.filename.clone(), |name| {
+                        name.to_string_lossy().to_string()
+                    }),
+                _ => format!("{} files", loading.len()),
+            };
+            let read: u64 = loading.iter().map(|progress| progress.read).sum();
+            let total: u64 = loading.iter().map(|progress| progress.total).sum();
+            let lines: usize = loading.iter().map(|progress| progress.lines).sum();
+            let elapsed = loading.iter().map(|progress| progress.elapsed).max();
+            let lines_per_second =
+                lines as f64 / elapsed.unwrap_or_default().as_secs_f64().max(0.001);
+            Self::render_tag(
+                &mut spans,
+                "Loading",
+                &format!(
+                    "{} {} / {} {:.0} lines/s",
+                    name,
+                    format_bytes(read),
+                    format_bytes(total),
+                    lines_per_second
+                ),
+                state.settings.colors.footer.other,
+                &state.settings.global.symbols,
+            );
+        }
+        if let Some(older) = &state.records.older {
+            Self::render_tag(
+                &mut spans,
+                "Tail",
+                &format!("from {}", format_bytes(older.start)),
+                state.settings.colors.footer.other,
+                &state.settings.global.symbols,
+            );
+        }
+        if !state.following {
+            Self::render_tag(
+                &mut spans,
+                "PAUSED",
+                &format!("+{} new", state.new_while_paused),
+                state.settings.colors.footer.other,
+                &state.settings.global.symbols,
+            );
+        }
+        if state.records.dropped > 0 {
+            Self::render_tag(
+                &mut spans,
+                "Dropped",
+                &state.records.dropped.to_string(),
+                state.settings.colors.footer.other,
+                &state.settings.global.symbols,
+            );
+        }
 
         let right_line = Line::from(spans);
 
@@ -771,6 +910,21 @@ mod tests {
         assert_eq!(line2.style.fg.unwrap(), Color::Red);
     }
 
+    #[test]
+    fn test_render_multiline_record() {
+        let state = TuiState::new().unwrap();
+        let record = record::Record::new("Error: boom\n  at main.rs:3".to_string());
+
+        // Single row in the table
+        let line = TuiChrome::render_record_original(&state, &record);
+        assert_eq!(line.spans.len(), 1);
+        assert_eq!(line.spans[0].content, "Error: boom       at main.rs:3");
+
+        // One line per physical line in the details view
+        let wrapped = TuiChrome::wrap_record_original(&record, 78);
+        assert_eq!(wrapped, vec!["Error: boom", "at main.rs:3"]);
+    }
+
     #[test]
     fn test_render_record_original_vscroll() {
         let original = "\x1b[32mINFO\x1b[0m\tLog line\t\x1b[31m\tError\x1b[0m";
@@ -85,6 +85,56 @@ pub fn reverse_style(style: Style) -> Style {
     new_style
 }
 
+/// Shows the control characters and invalid UTF-8 bytes of the line as `\x07` or,
+/// with `hex`, as `<07>`. Tabs, line ends and ANSI color sequences are kept.
+pub fn escape_nonprintable(line: &[u8], hex: bool) -> String {
+    let escape = |text: &mut String, byte: u8| {
+        if hex {
+            text.push_str(&format!("<{:02X}>", byte));
+        } else {
+            text.push_str(&format!("\\x{:02x}", byte));
+        }
+    };
+    let mut text = String::new();
+    for chunk in line.utf8_chunks() {
+        let mut chars = chunk.valid().chars().peekable();
+        while let Some(c) = chars.next() {
+            let keep = !c.is_control()
+                || c == '\t'
+                || c == '\n'
+                || (c == '\x1b' && chars.peek() == Some(&'['));
+            if keep {
+                text.push(c);
+            } else {
+                let mut buffer = [0; 4];
+                for byte in c.encode_utf8(&mut buffer).bytes() {
+                    escape(&mut text, byte);
+                }
+            }
+        }
+        for byte in chunk.invalid() {
+            escape(&mut text, *byte);
+        }
+    }
+    text
+}
+
+/// Size in bytes with a binary unit, as `1.5 MiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
 pub fn parse_tabs(text: &str) -> String {
     // check if has tabs, and not just return the string
     if !text.contains('\t') {
@@ -146,4 +196,23 @@ mod tests {
             "INFO    Log line        Error"
         );
     }
+    #[test]
+    fn test_escape_nonprintable() {
+        let line = b"caf\xe9\tbell\x07\r\x1b[31mred\x1b[0m";
+        assert_eq!(
+            escape_nonprintable(line, false),
+            "caf\\xe9\tbell\\x07\\x0d\x1b[31mred\x1b[0m"
+        );
+        assert_eq!(
+            escape_nonprintable(line, true),
+            "caf<E9>\tbell<07><0D>\x1b[31mred\x1b[0m"
+        );
+        assert_eq!(escape_nonprintable("ñu\n".as_bytes(), true), "ñu\n");
+    }
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
 }
@@ -0,0 +1,516 @@
+//! Typed field values
+//!
+//! Extractors can declare the type of a field (`%{NUMBER:bytes:int}` in grok, or
+//! the `types bytes=bytes duration=duration` extractor). Those fields are converted
+//! once when the record is parsed and the filters compare them with their type, so
+//! `duration > 0.3` is a float comparison and not a string one.
+
+use std::cmp::Ordering;
+
+use chrono::prelude::*;
+use chrono::NaiveDateTime;
+
+use crate::parser::ParserError;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Number(i64),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+    Timestamp(DateTime<Utc>),
+    /// A time of the day, compared with the time of the day of timestamps
+    Time(NaiveTime),
+    /// In seconds
+    Duration(f64),
+    Bytes(u64),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FieldType {
+    Int,
+    Float,
+    Bool,
+    Timestamp,
+    Duration,
+    Bytes,
+}
+
+impl FieldType {
+    pub fn new(s: &str) -> Result<FieldType, ParserError> {
+        match s {
+            "int" => Ok(FieldType::Int),
+            "float" => Ok(FieldType::Float),
+            "bool" => Ok(FieldType::Bool),
+            "timestamp" => Ok(FieldType::Timestamp),
+            "duration" => Ok(FieldType::Duration),
+            "bytes" => Ok(FieldType::Bytes),
+            _ => Err(ParserError::InvalidParser(format!("unknown type {}", s))),
+        }
+    }
+
+    /// Converts the extracted text. None if it is not valid for this type.
+    pub fn parse(&self, s: &str) -> Option<Value> {
+        let s = s.trim();
+        match self {
+            FieldType::Int => s.parse().ok().map(Value::Number),
+            FieldType::Float => s.parse().ok().map(Value::Float),
+            FieldType::Bool => parse_bool(s).map(Value::Boolean),
+            FieldType::Timestamp => parse_timestamp(s).map(Value::Timestamp),
+            FieldType::Duration => parse_duration(s).map(Value::Duration),
+            FieldType::Bytes => parse_bytes(s).map(Value::Bytes),
+        }
+    }
+}
+
+/// Parses the `field=type` list of the `types` extractor.
+pub fn parse_field_types(s: &str) -> Result<Vec<(String, FieldType)>, ParserError> {
+    s.split_whitespace()
+        .map(|declaration| match declaration.split_once('=') {
+            Some((field, field_type)) => Ok((field.to_string(), FieldType::new(field_type)?)),
+            None => Err(ParserError::InvalidParser(format!(
+                "types {} (expected field=type)",
+                declaration
+            ))),
+        })
+        .collect()
+}
+
+/// Value of a field that was not declared with a type, guessed from the text.
+pub fn guess(s: &str) -> Value {
+    if let Ok(n) = s.parse::<i64>() {
+        return Value::Number(n);
+    }
+    // Only plain decimals, not "inf", "nan" or "1e3"
+    if s.contains('.')
+        && s.bytes()
+            .all(|b| b.is_ascii_digit() || b == b'.' || b == b'-' || b == b'+')
+    {
+        if let Ok(f) = s.parse::<f64>() {
+            return Value::Float(f);
+        }
+    }
+    Value::String(s.to_string())
+}
+
+/// Compares two values with their types. Numeric types compare between them, and
+/// strings are converted to the type of the other side when possible.
+///
+/// None if the values can not be compared.
+pub fn compare(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Value::Number(lhs), Value::Number(rhs)) => Some(lhs.cmp(rhs)),
+        (Value::String(lhs), Value::String(rhs)) => Some(lhs.cmp(rhs)),
+        (Value::Boolean(lhs), Value::Boolean(rhs)) => Some(lhs.cmp(rhs)),
+        (Value::Timestamp(lhs), Value::Timestamp(rhs)) => Some(lhs.cmp(rhs)),
+        (Value::Timestamp(lhs), Value::String(rhs)) => match parse_timestamp(rhs) {
+            Some(rhs) => Some(lhs.cmp(&rhs)),
+            None => Some(lhs.to_rfc3339().as_str().cmp(rhs.as_str())),
+        },
+        (Value::String(_), Value::Timestamp(_)) => compare(rhs, lhs).map(Ordering::reverse),
+        (Value::Time(lhs), Value::Time(rhs)) => Some(lhs.cmp(rhs)),
+        (Value::Timestamp(lhs), Value::Time(rhs)) => Some(lhs.time().cmp(rhs)),
+        (Value::String(lhs), Value::Time(rhs)) => match parse_timestamp(lhs) {
+            Some(lhs) => Some(lhs.time().cmp(rhs)),
+            None => parse_time(lhs).map(|lhs| lhs.cmp(rhs)),
+        },
+        (Value::Time(_), Value::Timestamp(_) | Value::String(_)) => {
+            compare(rhs, lhs).map(Ordering::reverse)
+        }
+        (Value::Duration(lhs), Value::String(rhs)) => {
+            parse_duration(rhs).and_then(|rhs| lhs.partial_cmp(&rhs))
+        }
+        (Value::Bytes(lhs), Value::String(rhs)) => parse_bytes(rhs).map(|rhs| lhs.cmp(&rhs)),
+        (Value::Boolean(lhs), Value::String(rhs)) => parse_bool(rhs).map(|rhs| lhs.cmp(&rhs)),
+        // As untyped fields are guessed, `version == "1.2"` must match `version=1.2`
+        (Value::Number(_) | Value::Float(_), Value::String(rhs)) => {
+            match (as_f64(lhs), as_f64(&guess(rhs))) {
+                (Some(lhs), Some(rhs)) => lhs.partial_cmp(&rhs),
+                _ => None,
+            }
+        }
+        (
+            Value::String(_),
+            Value::Duration(_)
+            | Value::Bytes(_)
+            | Value::Boolean(_)
+            | Value::Number(_)
+            | Value::Float(_),
+        ) => compare(rhs, lhs).map(Ordering::reverse),
+        _ => match (as_f64(lhs), as_f64(rhs)) {
+            (Some(lhs), Some(rhs)) => lhs.partial_cmp(&rhs),
+            _ => None,
+        },
+    }
+}
+
+/// `lhs + rhs`, or `lhs - rhs` if `subtract`. Numbers add as numbers, and durations
+/// move timestamps and times of the day. Two timestamps subtract to a duration.
+///
+/// None if the values can not be added.
+pub fn add(lhs: &Value, rhs: &Value, subtract: bool) -> Option<Value> {
+    let sign = if subtract { -1.0 } else { 1.0 };
+    match (lhs, rhs) {
+        (Value::Timestamp(lhs), Value::Timestamp(rhs)) if subtract => {
+            let nanoseconds = (*lhs - *rhs).num_nanoseconds()?;
+            Some(Value::Duration(nanoseconds as f64 / 1e9))
+        }
+        (Value::Timestamp(_), Value::String(s)) if subtract => {
+            add(lhs, &Value::Timestamp(parse_timestamp(s)?), subtract)
+        }
+        (Value::Timestamp(timestamp), _) => {
+            let delta = chrono::Duration::nanoseconds((as_f64(rhs)? * sign * 1e9) as i64);
+            timestamp.checked_add_signed(delta).map(Value::Timestamp)
+        }
+        (Value::Time(time), _) => {
+            let delta = chrono::Duration::nanoseconds((as_f64(rhs)? * sign * 1e9) as i64);
+            Some(Value::Time(time.overflowing_add_signed(delta).0))
+        }
+        (Value::String(s), Value::Duration(_)) => {
+            // An untyped timestamp field
+            add(&Value::Timestamp(parse_timestamp(s)?), rhs, subtract)
+        }
+        (Value::Number(lhs), Value::Number(rhs)) if subtract => {
+            lhs.checked_sub(*rhs).map(Value::Number)
+        }
+        (Value::Number(lhs), Value::Number(rhs)) => lhs.checked_add(*rhs).map(Value::Number),
+        (Value::Duration(_), _) | (_, Value::Duration(_)) => {
+            Some(Value::Duration(as_f64(lhs)? + sign * as_f64(rhs)?))
+        }
+        _ => Some(Value::Float(as_f64(lhs)? + sign * as_f64(rhs)?)),
+    }
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => Some(*n as f64),
+        Value::Float(f) => Some(*f),
+        Value::Duration(d) => Some(*d),
+        Value::Bytes(b) => Some(*b as f64),
+        _ => None,
+    }
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Year of the syslog timestamps, that do not have it
+    static ref CURRENT_YEAR: i32 = Utc::now().year();
+}
+
+pub fn parse_timestamp(s: &str) -> Option<DateTime<Utc>> {
+    parse_timestamp_with_offset(s).map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Parses the timestamp formats of the logs, keeping the offset they are written
+/// with. Without one, they are in UTC.
+pub fn parse_timestamp_with_offset(s: &str) -> Option<DateTime<FixedOffset>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Some(dt);
+    }
+    // nginx/apache format
+    if let Ok(dt) = DateTime::parse_from_str(s, "%d/%b/%Y:%H:%M:%S %z") {
+        return Some(dt);
+    }
+    let naive_dt = parse_naive_timestamp(s)?;
+    Some(naive_dt.and_utc().fixed_offset())
+}
+
+/// Timestamps without timezone, or epoch seconds or milliseconds
+fn parse_naive_timestamp(s: &str) -> Option<NaiveDateTime> {
+    for format in ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"] {
+        if let Ok(naive_dt) = NaiveDateTime::parse_from_str(s, format) {
+            return Some(naive_dt);
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return date.and_hms_opt(0, 0, 0);
+    }
+    // Syslog format, as `Jan  2 12:30:45`, in the current year
+    if s.starts_with(|c: char| c.is_ascii_uppercase()) {
+        let mut parsed = chrono::format::Parsed::new();
+        let items = chrono::format::StrftimeItems::new("%b %e %H:%M:%S");
+        if chrono::format::parse(&mut parsed, s, items).is_ok()
+            && parsed.set_year(*CURRENT_YEAR as i64).is_ok()
+        {
+            return parsed.to_naive_datetime_with_offset(0).ok();
+        }
+    }
+    // Epoch seconds or milliseconds
+    if s.len() >= 9 && s.bytes().all(|b| b.is_ascii_digit()) {
+        let n: i64 = s.parse().ok()?;
+        let dt = if s.len() >= 13 {
+            DateTime::from_timestamp_millis(n)
+        } else {
+            DateTime::from_timestamp(n, 0)
+        };
+        return dt.map(|dt| dt.naive_utc());
+    }
+    None
+}
+
+/// Parses a bound of a time range, as any timestamp, `2025-03-01 10:00`, or only the
+/// time of the day as `10:30`, on the given date.
+pub fn parse_time_bound(s: &str, date: NaiveDate) -> Option<DateTime<Utc>> {
+    if let Some(timestamp) = parse_timestamp(s) {
+        return Some(timestamp);
+    }
+    if let Ok(naive_dt) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M") {
+        return Some(naive_dt.and_utc());
+    }
+    parse_time(s).map(|time| date.and_time(time).and_utc())
+}
+
+/// Parses a time of the day, as `10:30`, `10:30:15` or `10:30:15.250`.
+pub fn parse_time(s: &str) -> Option<NaiveTime> {
+    ["%H:%M:%S%.f", "%H:%M"]
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(s, format).ok())
+}
+
+/// Splits `1.5ms` into `(1.5, "ms")`
+fn split_number_unit(s: &str) -> Option<(f64, &str)> {
+    let end = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let number = s[..end].parse().ok()?;
+    Some((number, s[end..].trim_start()))
+}
+
+/// Parses durations as `350ms`, `1.5s`, `2m`, `1h30m` or a plain number of seconds.
+pub fn parse_duration(s: &str) -> Option<f64> {
+    let mut rest = s;
+    let mut total = 0.0;
+    while !rest.is_empty() {
+        let (number, unit_and_rest) = split_number_unit(rest)?;
+        let unit_end = unit_and_rest
+            .find(|c: char| !c.is_alphabetic())
+            .unwrap_or(unit_and_rest.len());
+        total += match &unit_and_rest[..unit_end] {
+            "ns" => number / 1e9,
+            "us" | "µs" => number / 1e6,
+            "ms" => number / 1e3,
+            "" | "s" => number,
+            "m" => number * 60.0,
+            "h" => number * 3600.0,
+            "d" => number * 86400.0,
+            _ => return None,
+        };
+        rest = &unit_and_rest[unit_end..];
+    }
+    if s.is_empty() {
+        None
+    } else {
+        Some(total)
+    }
+}
+
+/// Parses sizes as `1024`, `10KB`, `1.5MiB` or `2G`. Decimal units are powers of
+/// 1000 and binary units powers of 1024.
+pub fn parse_bytes(s: &str) -> Option<u64> {
+    let (number, unit) = split_number_unit(s)?;
+    let multiplier: f64 = match unit.to_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1e3,
+        "m" | "mb" => 1e6,
+        "g" | "gb" => 1e9,
+        "t" | "tb" => 1e12,
+        "kib" => 1024.0,
+        "mib" => 1024.0 * 1024.0,
+        "gib" => 1024.0 * 1024.0 * 1024.0,
+        "tib" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some((number * multiplier) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_type_parse() {
+        assert_eq!(FieldType::Int.parse("42"), Some(Value::Number(42)));
+        assert_eq!(FieldType::Int.parse("4.2"), None);
+        assert_eq!(FieldType::Float.parse("0.35"), Some(Value::Float(0.35)));
+        assert_eq!(FieldType::Bool.parse("yes"), Some(Value::Boolean(true)));
+        assert_eq!(
+            FieldType::Duration.parse("350ms"),
+            Some(Value::Duration(0.35))
+        );
+        assert_eq!(
+            FieldType::Duration.parse("1h30m"),
+            Some(Value::Duration(5400.0))
+        );
+        assert_eq!(FieldType::Duration.parse("2"), Some(Value::Duration(2.0)));
+        assert_eq!(FieldType::Duration.parse("fast"), None);
+        assert_eq!(FieldType::Bytes.parse("10KB"), Some(Value::Bytes(10_000)));
+        assert_eq!(FieldType::Bytes.parse("1.5KiB"), Some(Value::Bytes(1536)));
+        assert_eq!(FieldType::Bytes.parse("-"), None);
+
+        let expected = Utc.with_ymd_and_hms(2000, 10, 10, 20, 55, 36).unwrap();
+        assert_eq!(
+            FieldType::Timestamp.parse("10/Oct/2000:13:55:36 -0700"),
+            Some(Value::Timestamp(expected))
+        );
+        assert_eq!(
+            FieldType::Timestamp.parse("2000-10-10 20:55:36"),
+            Some(Value::Timestamp(expected))
+        );
+        assert_eq!(
+            FieldType::Timestamp.parse("971211336"),
+            Some(Value::Timestamp(expected))
+        );
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let expected = Utc.with_ymd_and_hms(2024, 1, 2, 12, 30, 45).unwrap();
+        for input in [
+            "2024-01-02T12:30:45Z",
+            "2024-01-02T13:30:45+01:00",
+            "02/Jan/2024:13:30:45 +0100",
+            "2024-01-02 12:30:45",
+            "2024-01-02T12:30:45",
+            "1704198645",
+            "1704198645000",
+        ] {
+            assert_eq!(parse_timestamp(input), Some(expected), "{}", input);
+        }
+        assert_eq!(
+            parse_timestamp("2024-01-02 12:30:45.250"),
+            Some(expected + chrono::Duration::milliseconds(250))
+        );
+        let syslog = parse_timestamp("Jan  2 12:30:45").unwrap();
+        assert_eq!(syslog.year(), Utc::now().year());
+        assert_eq!(syslog.time(), expected.time());
+        assert_eq!(parse_timestamp("Janitor 2"), None);
+        // The offset is kept, for the iso8601 transform
+        assert_eq!(
+            parse_timestamp_with_offset("02/Jan/2024:13:30:45 +0100")
+                .unwrap()
+                .to_rfc3339(),
+            "2024-01-02T13:30:45+01:00"
+        );
+    }
+
+    #[test]
+    fn test_parse_time_bound() {
+        let date = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        let expected = Utc.with_ymd_and_hms(2025, 3, 1, 10, 30, 0).unwrap();
+        assert_eq!(parse_time_bound("2025-03-01 10:30", date), Some(expected));
+        assert_eq!(
+            parse_time_bound("2025-03-01T10:30:00Z", date),
+            Some(expected)
+        );
+        assert_eq!(parse_time_bound("10:30", date), Some(expected));
+        assert_eq!(parse_time_bound("10:30:00", date), Some(expected));
+        assert_eq!(parse_time_bound("half past ten", date), None);
+    }
+
+    #[test]
+    fn test_parse_field_types() {
+        let types = parse_field_types("status=int duration=duration").unwrap();
+        assert_eq!(
+            types,
+            vec![
+                ("status".to_string(), FieldType::Int),
+                ("duration".to_string(), FieldType::Duration)
+            ]
+        );
+        assert!(parse_field_types("status").is_err());
+        assert!(parse_field_types("status=complex").is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(
+            compare(&Value::Float(0.35), &Value::Number(0)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare(&Value::Bytes(2048), &Value::String("1KiB".to_string())),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare(&Value::Duration(0.2), &Value::String("1s".to_string())),
+            Some(Ordering::Less)
+        );
+        let timestamp = Value::Timestamp(Utc.with_ymd_and_hms(2025, 1, 2, 0, 0, 0).unwrap());
+        assert_eq!(
+            compare(&timestamp, &Value::String("2025-01-01".to_string())),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare(&Value::String("2025-01-03".to_string()), &timestamp),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare(&Value::String("a".to_string()), &Value::Number(1)),
+            None
+        );
+        // Quoted numbers compare with guessed fields
+        assert_eq!(
+            compare(&Value::Float(1.2), &Value::String("1.2".to_string())),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            compare(&Value::String("10".to_string()), &Value::Number(200)),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare(&Value::Float(1.2), &Value::String("1.2.3".to_string())),
+            None
+        );
+    }
+
+    #[test]
+    fn test_add_and_times() {
+        let timestamp = Utc.with_ymd_and_hms(2025, 3, 1, 10, 5, 0).unwrap();
+        let time = NaiveTime::from_hms_opt(10, 0, 0).unwrap();
+        assert_eq!(
+            add(&Value::Timestamp(timestamp), &Value::Duration(300.0), true),
+            Some(Value::Timestamp(
+                Utc.with_ymd_and_hms(2025, 3, 1, 10, 0, 0).unwrap()
+            ))
+        );
+        assert_eq!(
+            add(
+                &Value::String("2025-03-01 10:06:00".to_string()),
+                &Value::Timestamp(timestamp),
+                false
+            ),
+            None
+        );
+        assert_eq!(
+            add(&Value::Time(time), &Value::Duration(90.0), false),
+            Some(Value::Time(NaiveTime::from_hms_opt(10, 1, 30).unwrap()))
+        );
+        assert_eq!(
+            add(&Value::Number(3), &Value::Number(1), true),
+            Some(Value::Number(2))
+        );
+        assert_eq!(
+            compare(&Value::Timestamp(timestamp), &Value::Time(time)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare(&Value::String("09:59".to_string()), &Value::Time(time)),
+            Some(Ordering::Less)
+        );
+    }
+
+    #[test]
+    fn test_guess() {
+        assert_eq!(guess("10"), Value::Number(10));
+        assert_eq!(guess("0.35"), Value::Float(0.35));
+        assert_eq!(guess("1.2.3"), Value::String("1.2.3".to_string()));
+        assert_eq!(guess("nan"), Value::String("nan".to_string()));
+    }
+}
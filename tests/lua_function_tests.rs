@@ -194,6 +194,39 @@ fn test_marking_functions() {
     println!("✓ Marking functions working correctly");
 }
 
+#[test]
+fn test_follow_pause_and_resume() {
+    let mut engine = LuaEngine::new().unwrap();
+    engine.initialize().unwrap();
+    let mut state = create_test_state_with_records();
+    state.position = 10;
+
+    // Following, the selection stays at the tail
+    state
+        .records
+        .add_record(Record::new("new line".into()), None);
+    state.records_added(1);
+    assert_eq!(state.position, 11);
+
+    // Paused, new records are only counted
+    compile_and_execute_script(&mut engine, &mut state, "follow(false)").unwrap();
+    assert!(!state.following);
+    for i in 0..3 {
+        state
+            .records
+            .add_record(Record::new(format!("paused {}", i)), None);
+        state.records_added(1);
+    }
+    assert_eq!(state.position, 11);
+    assert_eq!(state.new_while_paused, 3);
+
+    // Resuming jumps to the tail. Without argument it toggles.
+    compile_and_execute_script(&mut engine, &mut state, "follow()").unwrap();
+    assert!(state.following);
+    assert_eq!(state.position, 14);
+    assert_eq!(state.new_while_paused, 0);
+}
+
 #[test]
 fn test_mode_and_ui_functions() {
     println!("Testing mode switching and UI functions (mode, toggle_details)");
@@ -244,6 +277,14 @@ fn test_mode_and_ui_functions() {
         "toggle_details() should toggle view_details"
     );
 
+    // Test toggle_binary_display function
+    compile_and_execute_script(&mut engine, &mut state, "toggle_binary_display()").unwrap();
+    assert_eq!(
+        state.binary_display,
+        tailtales::settings::BinaryDisplay::Escape,
+        "toggle_binary_display() should cycle to escapes"
+    );
+
     println!("✓ Mode and UI functions working correctly");
 }
 
@@ -1165,6 +1206,8 @@ fn test_function_registration() {
         "move_to_prev_mark",
         "mode",
         "toggle_details",
+        "toggle_binary_display",
+        "follow",
         "refresh_screen",
         "clear_records",
         "hmove",
@@ -1181,6 +1224,8 @@ fn test_function_registration() {
         "get_record",
         "get_record_data",
         "lua_repl",
+        "save_filter",
+        "load_filter",
         // Functions from _init.lua
         "dir",
         "help",